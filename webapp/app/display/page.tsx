@@ -3,8 +3,9 @@
 import { Maximize2, Minimize2 } from 'lucide-react';
 import { useState, useEffect, useRef } from 'react';
 import { useTauriEvents } from '@/context/TauriListener';
-import { HistoryItem } from '@/lib/mocData';
+import { Emergency, HistoryItem } from '@/lib/mocData';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { useAnnouncementContext } from '@/context/AnnoncementsContext';
 
 export default function Display() {
@@ -14,6 +15,7 @@ export default function Display() {
     const mainRef = useRef(null);
     const { guichet, compteur } = useTauriEvents();
     const {announcements} = useAnnouncementContext();
+    const [emergency, setEmergency] = useState<Emergency | null>(null);
 
     const toggleFullScreen = async () => {
 
@@ -48,9 +50,27 @@ export default function Display() {
 
     }, [compteur])
 
+    useEffect(() => {
+        invoke<Emergency | null>("get_emergency").then(setEmergency);
+
+        const unlisten = listen<Emergency | null>('emergency', (event) => {
+            setEmergency(event.payload);
+        });
+        return () => {
+            unlisten.then((f) => f());
+        };
+    }, [])
+
     return (
         <>
             <main className="flex-1 flex flex-col relative" ref={mainRef}>
+                {
+                    emergency &&
+                    <div className="absolute inset-0 z-50 flex flex-col items-center justify-center gap-8 bg-red-700 p-16 text-white">
+                        <span className="text-3xl font-bold uppercase tracking-widest">Emergency</span>
+                        <p className="text-6xl font-bold text-center leading-tight">{emergency.message}</p>
+                    </div>
+                }
                 <div className="flex-1 flex bg-sidebar-foreground">
                     <div className="flex-1 flex flex-col items-center justify-center p-8 relative overflow-hidden">
                         <div className="relative z-10 display-glow rounded-2xl border-2 border-primary py-10 px-20 mb-8 flex flex-col gap-8 min-w-[350px]">
@@ -108,7 +128,7 @@ export default function Display() {
                     <div className="flex-1 overflow-hidden relative">
                         <div className="ticker-scroll whitespace-nowrap text-display-foreground/90 text-lg">
                         {announcements.map((announcement) => (
-                            announcement.active ?
+                            announcement.visible ?
                                 <span className="mx-20" key={announcement.id}>{announcement.message}</span> : null
                         ))}
                         </div>
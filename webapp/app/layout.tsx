@@ -6,6 +6,7 @@ import "./globals.css";
 import SideBar from "@/components/ui/sidebar";
 import { TauriProvider } from "@/context/TauriListener";
 import { AnnouncementsProvider } from "@/context/AnnoncementsContext";
+import { AuthProvider } from "@/context/AuthContext";
 
 const geistSans = Geist({
   variable: "--font-geist-sans",
@@ -37,7 +38,9 @@ export default function RootLayout({
           <SideBar />
           <TauriProvider>
             <AnnouncementsProvider>
-              {children}
+              <AuthProvider>
+                {children}
+              </AuthProvider>
             </AnnouncementsProvider>
           </TauriProvider>
           <Toaster />
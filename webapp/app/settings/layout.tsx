@@ -0,0 +1,10 @@
+import LoginGate from "@/components/ui/login-gate";
+
+// Everything on this page needs a staff session.
+export default function SettingsLayout({
+  children,
+}: Readonly<{
+  children: React.ReactNode;
+}>) {
+  return <LoginGate>{children}</LoginGate>;
+}
@@ -7,7 +7,7 @@ import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 import { Switch } from "@/components/ui/switch";
 import { Table, TableBody, TableCell, TableHead, TableHeader, TableRow } from "@/components/ui/table";
-import { cn } from "@/lib/utils";
+import { cn, errorMessage } from "@/lib/utils";
 import { Check, Copy, Delete, Key, Megaphone, Palette, Pencil, Plus, Settings2, Trash2, Wifi, WifiOff, X } from "lucide-react";
 import { useState, useEffect } from "react";
 import { toast } from "sonner";
@@ -37,6 +37,9 @@ export default function Settings() {
             setDevices(get_devices);
         } catch (e) {
             console.log(e);
+            toast.error("Failed to load devices", {
+                description: errorMessage(e)
+            })
         }
 
     }
@@ -48,7 +51,7 @@ export default function Settings() {
                 console.log(machineIp);
                 setIpAddr(machineIp);
             } catch (e) {
-                console.log('Error while getting ip address ... ');
+                console.log('Error while getting ip address ... ', errorMessage(e));
 
             }
         }
@@ -149,7 +152,7 @@ export default function Settings() {
                                                 } catch (e) {
                                                     console.error(e);
                                                     toast.error("Fail to delete", {
-                                                        description: "Decive has not been deleted ..."
+                                                        description: errorMessage(e)
                                                     })
                                                 }
 
@@ -204,7 +207,7 @@ export default function Settings() {
                                 } catch (e) {
                                     console.error(e);
                                     toast.error("Fail to add", {
-                                        description: "Fail to add a device ..."
+                                        description: errorMessage(e)
                                     })
 
                                 }
@@ -287,7 +290,7 @@ export default function Settings() {
                             } catch (e) {
                                 console.error(e);
                                 toast.error("Failed to add", {
-                                    description: "New announcement has not been added ...",
+                                    description: errorMessage(e),
                                 });
                             }
                         }}
@@ -333,6 +336,10 @@ export default function Settings() {
                                             refreshAnnouncements();
                                         } catch (e) {
                                             console.error(e);
+                                            refreshAnnouncements();
+                                            toast.error("Failed to update", {
+                                                description: errorMessage(e),
+                                            });
                                         }
                                     }}
                                 />
@@ -365,7 +372,7 @@ export default function Settings() {
                                                 } catch (e) {
                                                     console.error(e);
                                                     toast.error("Failed to update", {
-                                                        description: "Changes have not been saved ...",
+                                                        description: errorMessage(e),
                                                     });
                                                 }
                                             }}
@@ -418,7 +425,7 @@ export default function Settings() {
                                                 } catch (e) {
                                                     console.error(e);
                                                     toast.error("Failed to delete", {
-                                                        description: "The announcement has not been removed ...",
+                                                        description: errorMessage(e),
                                                     });
                                                 }
                                             }}
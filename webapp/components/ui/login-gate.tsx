@@ -0,0 +1,123 @@
+"use client";
+
+import { Button } from "@/components/ui/button";
+import { Card, CardContent, CardDescription, CardHeader, CardTitle } from "@/components/ui/card";
+import { Input } from "@/components/ui/input";
+import { Label } from "@/components/ui/label";
+import { useAuthContext } from "@/context/AuthContext";
+import { errorMessage } from "@/lib/utils";
+import { Lock, LogOut, ShieldCheck, UserRound } from "lucide-react";
+import { useState } from "react";
+import { toast } from "sonner";
+
+// Shows its children only to a logged-in staff member. On first start, asks for the
+// admin account instead; the backend refuses everything else until it exists.
+const LoginGate = ({ children }: { children: React.ReactNode }) => {
+  const { status, login, logout, createFirstAdmin } = useAuthContext();
+  const [name, setName] = useState("");
+  const [secret, setSecret] = useState("");
+  const [confirm, setConfirm] = useState("");
+  const [busy, setBusy] = useState(false);
+
+  if (!status) {
+    return null;
+  }
+
+  if (status.session) {
+    const session = status.session;
+    return (
+      <div className="flex flex-col w-full">
+        <div className="flex items-center justify-end gap-3 px-8 pt-4 text-sm text-muted-foreground">
+          <UserRound className="w-4 h-4" />
+          <span>
+            {session.name} • <span className="capitalize">{session.role}</span>
+          </span>
+          <Button
+            variant="outline"
+            size="sm"
+            className="gap-2"
+            onClick={async () => {
+              try {
+                await logout();
+              } catch (e) {
+                toast.error("Failed to log out", { description: errorMessage(e) });
+              }
+            }}
+          >
+            <LogOut className="w-3 h-3" />
+            Log out
+          </Button>
+        </div>
+        {children}
+      </div>
+    );
+  }
+
+  const setup = status.needs_setup;
+
+  return (
+    <main className="p-8 w-full flex items-center justify-center">
+      <Card className="w-full max-w-md">
+        <CardHeader>
+          <CardTitle className="flex items-center gap-2">
+            {setup ? <ShieldCheck className="w-5 h-5 text-accent" /> : <Lock className="w-5 h-5 text-accent" />}
+            {setup ? "Create the admin account" : "Log in"}
+          </CardTitle>
+          <CardDescription>
+            {setup
+              ? "First start: this account manages staff, devices and settings."
+              : "Admins and supervisors use their password; operators can use their PIN."}
+          </CardDescription>
+        </CardHeader>
+        <CardContent>
+          <form
+            className="space-y-4"
+            onSubmit={async (e) => {
+              e.preventDefault();
+              if (setup && secret !== confirm) {
+                toast.error("Passwords do not match");
+                return;
+              }
+              setBusy(true);
+              try {
+                if (setup) {
+                  await createFirstAdmin(name.trim(), secret);
+                } else {
+                  await login(name.trim(), secret);
+                }
+                setSecret("");
+                setConfirm("");
+              } catch (e) {
+                toast.error(setup ? "Failed to create the account" : "Login failed", {
+                  description: errorMessage(e),
+                });
+              } finally {
+                setBusy(false);
+              }
+            }}
+          >
+            <div className="space-y-2">
+              <Label htmlFor="loginName">Name</Label>
+              <Input id="loginName" autoFocus value={name} onChange={(e) => setName(e.target.value)} />
+            </div>
+            <div className="space-y-2">
+              <Label htmlFor="loginSecret">{setup ? "Password" : "Password or PIN"}</Label>
+              <Input id="loginSecret" type="password" value={secret} onChange={(e) => setSecret(e.target.value)} />
+            </div>
+            {setup && (
+              <div className="space-y-2">
+                <Label htmlFor="loginConfirm">Confirm password</Label>
+                <Input id="loginConfirm" type="password" value={confirm} onChange={(e) => setConfirm(e.target.value)} />
+              </div>
+            )}
+            <Button type="submit" className="w-full" disabled={busy || !name.trim() || !secret}>
+              {setup ? "Create account" : "Log in"}
+            </Button>
+          </form>
+        </CardContent>
+      </Card>
+    </main>
+  );
+};
+
+export default LoginGate;
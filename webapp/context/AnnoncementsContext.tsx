@@ -2,6 +2,7 @@
 
 import { Announcement } from '@/lib/mocData';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { createContext, Dispatch, SetStateAction, useCallback, useContext, useEffect, useState } from 'react';
 
 interface AnnouncementsContextType {
@@ -34,6 +35,14 @@ export const AnnouncementsProvider = ({ children }: { children: React.ReactNode
 
     useEffect(() => {
         refreshAnnouncements();
+
+        // The scheduler pushes the list whenever an annonce goes on or off air.
+        const unlisten = listen<Announcement[]>('annonces-updated', (event) => {
+            setAnnouncements(event.payload);
+        });
+        return () => {
+            unlisten.then((f) => f());
+        };
     }, [refreshAnnouncements]);
 
     return (
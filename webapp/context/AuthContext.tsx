@@ -0,0 +1,69 @@
+'use client';
+
+import { AuthStatus, Session } from '@/lib/mocData';
+import { invoke } from '@tauri-apps/api/core';
+import { createContext, useCallback, useContext, useEffect, useState } from 'react';
+
+// The backend locks the session after 30 minutes without use; checking this often
+// brings the login screen back soon after.
+const STATUS_POLL_MS = 60_000;
+
+interface AuthContextType {
+    // null until the first answer from the backend.
+    status: AuthStatus | null;
+    refreshAuth: () => Promise<void>;
+    login: (name: string, secret: string) => Promise<Session>;
+    logout: () => Promise<void>;
+    createFirstAdmin: (name: string, password: string) => Promise<Session>;
+}
+
+const AuthContext = createContext<AuthContextType | undefined>(undefined);
+
+export const useAuthContext = () => {
+    const context = useContext(AuthContext);
+    if (!context) {
+        throw new Error("useAuthContext must be used within an AuthProvider");
+    }
+    return context;
+};
+
+export const AuthProvider = ({ children }: { children: React.ReactNode }) => {
+    const [status, setStatus] = useState<AuthStatus | null>(null);
+
+    const refreshAuth = useCallback(async () => {
+        try {
+            setStatus(await invoke<AuthStatus>("get_auth_status"));
+        } catch (error) {
+            console.error("Failed to fetch auth status:", error);
+        }
+    }, []);
+
+    const login = useCallback(async (name: string, secret: string) => {
+        const session = await invoke<Session>("login", { name, secret });
+        setStatus({ session, needs_setup: false });
+        return session;
+    }, []);
+
+    const logout = useCallback(async () => {
+        await invoke("logout");
+        setStatus((prev) => prev && { ...prev, session: null });
+    }, []);
+
+    const createFirstAdmin = useCallback(async (name: string, password: string) => {
+        const session = await invoke<Session>("create_first_admin", { name, password });
+        setStatus({ session, needs_setup: false });
+        return session;
+    }, []);
+
+    useEffect(() => {
+        refreshAuth();
+        const timer = setInterval(refreshAuth, STATUS_POLL_MS);
+        return () => clearInterval(timer);
+    }, [refreshAuth]);
+
+    return (
+        <AuthContext.Provider value={{ status, refreshAuth, login, logout, createFirstAdmin }}>
+            {children}
+        </AuthContext.Provider>
+    );
+}
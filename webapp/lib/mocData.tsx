@@ -1,27 +1,256 @@
 
 
+// What a failed `invoke` rejects with (QmsError on the Rust side).
+export interface QmsError {
+    kind: "database" | "migration" | "unauthorized" | "forbidden" | "validation" | "not_found" | "locked" | "internal";
+    message: string;
+}
+
 export interface HistoryItem {
     id: number,
     ticket_number: number,
     desk_name: String,
+    service?: string | null,
+    operator_id?: number | null,
+    operator_name?: string | null,
+    status?: "called" | "recalled" | "served" | "no_show",
     created_at: String,
 }
 
+export interface HistoryQuery {
+    from?: string;
+    to?: string;
+    desk?: string;
+    service?: string;
+    operator_id?: number;
+    status?: HistoryItem["status"];
+    search?: string;
+    since_reset?: boolean;
+    offset?: number;
+    limit?: number;
+}
+
+export interface HistoryPage {
+    items: HistoryItem[];
+    total: number;
+    offset: number;
+    limit: number;
+}
+
 export interface Device {
     id: number;
     name: string;
+    service?: string | null;
+    group?: string | null;
     ipAddress?: string;
     status?: "connected" | "disconnected";
     token: string;
 }
 
-export interface Announcement {
+export interface TimeWindow {
+    start_date?: string | null;
+    end_date?: string | null;
+    time_start?: string | null;
+    time_end?: string | null;
+    days?: number[];
+}
+
+export interface AnnouncementSchedule extends TimeWindow {
+    display_seconds?: number;
+    priority?: number;
+}
+
+export interface AnnouncementSpeech {
+    audible?: boolean;
+    repeat_minutes?: number | null;
+    speak_times?: string[];
+}
+
+// Empty lists show the announcement on every screen.
+export interface AnnouncementTargets {
+    devices?: string[];
+    groups?: string[];
+    services?: string[];
+}
+
+export interface Announcement extends AnnouncementSchedule, AnnouncementSpeech {
     id: string;
     message: string;
     active: boolean;
+    targets?: AnnouncementTargets;
+    visible?: boolean;
+}
+
+export interface Slide extends TimeWindow {
+    id: number;
+    kind: "image" | "video" | "text";
+    file_name?: string | null;
+    url?: string | null;
+    text?: string | null;
+    duration_seconds: number;
+    position: number;
+    active: boolean;
+    visible: boolean;
+}
+
+// Durations in minutes; null when no ticket end is known.
+export interface StatsSummary {
+    served: number;
+    no_shows: number;
+    avg_service_minutes: number | null;
+    median_service_minutes: number | null;
+    p90_service_minutes: number | null;
+    idle_minutes: number;
+    breaks: number;
+    break_minutes: number;
+}
+
+export interface StatsRange {
+    from: string;
+    to: string;
+    site: StatsSummary;
+    desks: (StatsSummary & { name: string })[];
+    services: (StatsSummary & { name: string })[];
+    operators: (StatsSummary & { name: string })[];
+}
+
+export type Role = "operator" | "supervisor" | "admin";
+
+export interface Staff {
+    id: number;
+    name: string;
+    role: Role;
+    active: boolean;
+    has_pin: boolean;
+    has_password: boolean;
+}
+
+export interface Session {
+    staff_id: number;
+    name: string;
+    role: Role;
+    started_at: string;
+}
+
+export interface AuthStatus {
+    session: Session | null;
+    // No admin account yet: create_first_admin must be called first.
+    needs_setup: boolean;
+}
+
+export interface DeskSession {
+    id: number;
+    desk_name: string;
+    staff_id: number;
+    staff_name: string;
+    started_at: string;
+    ended_at: string | null;
+}
+
+// [weekday 0 = Monday][hour 0-23]
+export type HeatmapMatrix = number[][];
+
+export interface Heatmap {
+    from: string;
+    to: string;
+    calls: HeatmapMatrix;
+    arrivals: HeatmapMatrix | null;
+    calls_by_hour: number[];
+    calls_by_weekday: number[];
+    weekday_days: number[];
+}
+
+export interface HeatmapComparison {
+    first: Heatmap;
+    second: Heatmap;
+    calls_change: HeatmapMatrix;
+}
+
+export interface SlaTarget {
+    wait_minutes: number;
+    service_minutes: number;
+}
+
+export interface SlaSettings {
+    default: SlaTarget;
+    services: Record<string, SlaTarget>;
+}
+
+export interface DurationStats {
+    count: number;
+    avg_minutes: number | null;
+    median_minutes: number | null;
+    p90_minutes: number | null;
+    target_minutes: number;
+    within_target_percent: number | null;
+}
+
+export interface WaitingStats {
+    service: string | null;
+    called: number;
+    no_shows: number;
+    no_show_percent: number | null;
+    waiting: DurationStats;
+    serving: DurationStats;
+}
+
+export interface WaitingMetrics {
+    from: string;
+    to: string;
+    site: WaitingStats;
+    services: WaitingStats[];
+}
+
+export interface Emergency {
+  message: string;
+  started_at: string;
 }
 
 export interface TicketCall {
   guichet: string;
   compteur: number;
-}
\ No newline at end of file
+  recall?: boolean;
+  // Only on the `call_audio` event that follows the call.
+  audio_url?: string;
+}
+
+export type ExportFormat = "csv" | "json" | "xlsx";
+
+export interface AuditEntry {
+    id: number;
+    action: string;
+    // Staff member or device; null for a failed login.
+    actor: string | null;
+    staff_id: number | null;
+    // "admin app", or the device's IP address.
+    source: string | null;
+    before: unknown | null;
+    after: unknown | null;
+    created_at: string;
+}
+
+export interface AuditQuery {
+    from?: string;
+    to?: string;
+    action?: string;
+    actor?: string;
+    staff_id?: number;
+    offset?: number;
+    limit?: number;
+}
+
+export interface AuditPage {
+    items: AuditEntry[];
+    total: number;
+    offset: number;
+    limit: number;
+}
+
+// Daily, or Monday to Sunday.
+export type ReportPeriod = "daily" | "weekly";
+
+// Where `generate_report` saved the report.
+export interface ReportFiles {
+    html: string;
+    pdf: string;
+}
@@ -0,0 +1,586 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tts::Tts;
+
+use crate::audio::{AudioOutput, SoundEvent, SoundSettings};
+use crate::error::{QmsError, QmsResult};
+use crate::spelling;
+use crate::voicepack::{Segment, VoicePacks};
+
+// How often the worker checks whether the engine finished the current phrase.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Used to wait out a phrase on engines that cannot report `is_speaking`.
+const FALLBACK_MS_PER_CHAR: u64 = 90;
+// Silence between two readings of an emergency alarm.
+const ALARM_PAUSE: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Announcement {
+    Call {
+        ticket: i32,
+        desk: String,
+        service: Option<String>,
+    },
+    Recall {
+        ticket: i32,
+        desk: String,
+        service: Option<String>,
+    },
+    // Free text, spoken as written. Urgent messages go ahead of waiting calls.
+    Message {
+        text: String,
+        urgent: bool,
+    },
+    // A scheduled annonce read aloud. Waits behind every call, even later ones.
+    Annonce {
+        id: i32,
+        text: String,
+    },
+    // The event sound alone (used to preview sound settings).
+    Chime(SoundEvent),
+}
+
+impl Announcement {
+    pub fn sound(&self) -> SoundEvent {
+        match self {
+            Announcement::Call { .. } => SoundEvent::Call,
+            Announcement::Recall { .. } => SoundEvent::Recall,
+            Announcement::Message { urgent: true, .. } => SoundEvent::Urgent,
+            Announcement::Message { urgent: false, .. } | Announcement::Annonce { .. } => SoundEvent::Call,
+            Announcement::Chime(event) => *event,
+        }
+    }
+
+    fn is_urgent(&self) -> bool {
+        matches!(self, Announcement::Message { urgent: true, .. })
+    }
+
+    fn is_background(&self) -> bool {
+        matches!(self, Announcement::Annonce { .. })
+    }
+
+    // A recall is redundant while the same ticket is still waiting to be spoken, and an
+    // annonce while it is still waiting from its previous reading.
+    fn is_duplicate_of(&self, other: &Announcement) -> bool {
+        match (self, other) {
+            (Announcement::Annonce { id, .. }, Announcement::Annonce { id: other, .. }) => id == other,
+            (
+                Announcement::Recall { ticket, desk, .. },
+                Announcement::Call { ticket: t, desk: d, .. }
+                | Announcement::Recall { ticket: t, desk: d, .. },
+            ) => ticket == t && desk == d,
+            _ => false,
+        }
+    }
+}
+
+// One spoken version of a call. A call is read once per entry, in list order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LanguageAnnouncement {
+    // BCP 47 tag (e.g. "fr-FR", "ln"): picks the number spelling, and the voice when
+    // `voice_id` is not set.
+    pub language: String,
+    // Placeholders: {ticket}, {desk}, {service}
+    pub template: String,
+    pub voice_id: Option<String>,
+    // Recorded voice pack to assemble the phrase from; the TTS voice above reads
+    // only the words it has no clip for.
+    pub voice_pack: Option<String>,
+}
+
+impl LanguageAnnouncement {
+    // `None` when this announcement has nothing to say in this language.
+    pub fn render(&self, announcement: &Announcement) -> Option<String> {
+        let text = match announcement {
+            Announcement::Call {
+                ticket,
+                desk,
+                service,
+            }
+            | Announcement::Recall {
+                ticket,
+                desk,
+                service,
+            } => self
+                .template
+                .replace("{ticket}", &spelling::spell_ticket(&ticket.to_string(), &self.language))
+                .replace("{desk}", &spelling::spell_numbers_in(desk, &self.language))
+                .replace("{service}", service.as_deref().unwrap_or("")),
+            Announcement::Message { text, .. } | Announcement::Annonce { text, .. } => text.clone(),
+            Announcement::Chime(_) => return None,
+        };
+        Some(text)
+    }
+}
+
+// Persisted under `settings::TTS`. Rate and pitch are multipliers of the engine's
+// normal value (1.0 = unchanged) because every backend uses its own scale.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TtsSettings {
+    pub enabled: bool,
+    pub languages: Vec<LanguageAnnouncement>,
+    pub rate: f32,
+    pub pitch: f32,
+    // 0.0 (silent) to 1.0 (engine maximum)
+    pub volume: f32,
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        TtsSettings {
+            enabled: true,
+            languages: vec![
+                LanguageAnnouncement {
+                    language: "fr-FR".to_string(),
+                    template: "Client numéro {ticket}, au guichet {desk}".to_string(),
+                    voice_id: None,
+                    voice_pack: None,
+                },
+                LanguageAnnouncement {
+                    language: "en-US".to_string(),
+                    template: "Client number {ticket}, to desk {desk}".to_string(),
+                    voice_id: None,
+                    voice_pack: None,
+                },
+                LanguageAnnouncement {
+                    language: "ln".to_string(),
+                    template: "Kasi {ticket}, kende na guichet {desk}".to_string(),
+                    voice_id: None,
+                    voice_pack: None,
+                },
+            ],
+            rate: 1.0,
+            pitch: 1.0,
+            volume: 1.0,
+        }
+    }
+}
+
+impl TtsSettings {
+    pub fn validate(&self) -> QmsResult<()> {
+        if self.languages.is_empty() {
+            return Err(QmsError::Validation("At least one announcement language is required".to_string()));
+        }
+        for entry in &self.languages {
+            if entry.language.trim().is_empty() {
+                return Err(QmsError::Validation("Announcement language cannot be empty".to_string()));
+            }
+            if entry.template.trim().is_empty() {
+                return Err(QmsError::Validation(format!(
+                    "TTS template for '{}' cannot be empty",
+                    entry.language
+                )));
+            }
+        }
+        if !(0.25..=4.0).contains(&self.rate) {
+            return Err(QmsError::Validation("TTS rate must be between 0.25 and 4".to_string()));
+        }
+        if !(0.0..=2.0).contains(&self.pitch) {
+            return Err(QmsError::Validation("TTS pitch must be between 0 and 2".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.volume) {
+            return Err(QmsError::Validation("TTS volume must be between 0 and 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct VoiceInfo {
+    id: String,
+    name: String,
+    language: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AnnouncerStatus {
+    pending: usize,
+    speaking: bool,
+}
+
+enum Command {
+    Announce(Job),
+    Configure(TtsSettings),
+    ConfigureSounds(SoundSettings),
+    // Loops the message as an urgent announcement until `Alarm(None)`.
+    Alarm(Option<String>),
+}
+
+// A queued announcement. Previews carry the (possibly unsaved) settings to try out.
+struct Job {
+    announcement: Announcement,
+    tts: Option<TtsSettings>,
+    sounds: Option<SoundSettings>,
+}
+
+// Cheap handle used by HTTP handlers and Tauri commands. Sending never blocks:
+// the worker thread owns the TTS engine and speaks one phrase at a time.
+#[derive(Clone)]
+pub struct Announcer {
+    tx: mpsc::Sender<Command>,
+    pending: Arc<AtomicUsize>,
+    speaking: Arc<AtomicBool>,
+    // Read once at startup: querying the engine from here would mean waiting for the
+    // worker to finish speaking.
+    voices: Arc<Vec<VoiceInfo>>,
+}
+
+impl Announcer {
+    pub fn spawn(
+        tts: Option<Tts>,
+        settings: TtsSettings,
+        sounds: SoundSettings,
+        packs: VoicePacks,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let speaking = Arc::new(AtomicBool::new(false));
+
+        let voices = tts
+            .as_ref()
+            .filter(|tts| tts.supported_features().voice)
+            .and_then(|tts| tts.voices().ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|voice| VoiceInfo {
+                id: voice.id(),
+                name: voice.name(),
+                language: voice.language().to_string(),
+            })
+            .collect();
+
+        let worker = Worker {
+            rx,
+            tts,
+            settings,
+            sounds,
+            packs,
+            alarm: None,
+            queue: VecDeque::new(),
+            pending: pending.clone(),
+            speaking: speaking.clone(),
+        };
+
+        thread::Builder::new()
+            .name("announcer".to_string())
+            .spawn(move || {
+                // The output stream cannot leave the thread that opened it.
+                let audio = AudioOutput::open();
+                worker.run(audio.as_ref())
+            })
+            .expect("failed to spawn announcer thread");
+
+        Announcer {
+            tx,
+            pending,
+            speaking,
+            voices: Arc::new(voices),
+        }
+    }
+
+    pub fn announce(&self, announcement: Announcement) {
+        self.enqueue(Job {
+            announcement,
+            tts: None,
+            sounds: None,
+        });
+    }
+
+    // Plays a sample with the given settings, queued like any other call.
+    pub fn preview(
+        &self,
+        announcement: Announcement,
+        tts: Option<TtsSettings>,
+        sounds: Option<SoundSettings>,
+    ) {
+        self.enqueue(Job {
+            announcement,
+            tts,
+            sounds,
+        });
+    }
+
+    // Takes effect from the next phrase on; the current one is never cut.
+    pub fn configure(&self, settings: TtsSettings) {
+        let _ = self.tx.send(Command::Configure(settings));
+    }
+
+    pub fn configure_sounds(&self, sounds: SoundSettings) {
+        let _ = self.tx.send(Command::ConfigureSounds(sounds));
+    }
+
+    // Starts (or replaces) the emergency alarm, dropping everything queued; `None`
+    // stops it after the current reading.
+    pub fn alarm(&self, message: Option<String>) {
+        let _ = self.tx.send(Command::Alarm(message));
+    }
+
+    pub fn voices(&self) -> Vec<VoiceInfo> {
+        self.voices.as_ref().clone()
+    }
+
+    pub fn status(&self) -> AnnouncerStatus {
+        AnnouncerStatus {
+            pending: self.pending.load(Ordering::SeqCst),
+            speaking: self.speaking.load(Ordering::SeqCst),
+        }
+    }
+
+    fn enqueue(&self, job: Job) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if self.tx.send(Command::Announce(job)).is_err() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            eprintln!("❌ Announcer stopped, announcement dropped");
+        }
+    }
+}
+
+struct Worker {
+    rx: mpsc::Receiver<Command>,
+    tts: Option<Tts>,
+    settings: TtsSettings,
+    sounds: SoundSettings,
+    packs: VoicePacks,
+    alarm: Option<String>,
+    queue: VecDeque<Job>,
+    pending: Arc<AtomicUsize>,
+    speaking: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn run(mut self, audio: Option<&AudioOutput>) {
+        loop {
+            if let Some(message) = self.alarm.clone() {
+                self.play(
+                    &Job {
+                        announcement: Announcement::Message {
+                            text: message,
+                            urgent: true,
+                        },
+                        tts: None,
+                        sounds: None,
+                    },
+                    audio,
+                );
+                match self.rx.recv_timeout(ALARM_PAUSE) {
+                    Ok(command) => self.handle(command),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+                self.drain();
+                continue;
+            }
+
+            // Sleep until something arrives when there is nothing left to say.
+            if self.queue.is_empty() {
+                match self.rx.recv() {
+                    Ok(command) => self.handle(command),
+                    Err(_) => return,
+                }
+            }
+            self.drain();
+
+            if let Some(job) = self.queue.pop_front() {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                self.play(&job, audio);
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Configure(settings) => self.settings = settings,
+            Command::ConfigureSounds(sounds) => self.sounds = sounds,
+            Command::Alarm(message) => {
+                if message.is_some() {
+                    // Nothing queued before the emergency is still relevant after it.
+                    self.pending.fetch_sub(self.queue.len(), Ordering::SeqCst);
+                    self.queue.clear();
+                }
+                self.alarm = message;
+            }
+            Command::Announce(_) if self.alarm.is_some() => {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            Command::Announce(job) => {
+                let duplicate = self
+                    .queue
+                    .iter()
+                    .any(|queued| job.announcement.is_duplicate_of(&queued.announcement));
+                if duplicate {
+                    println!("🔁 Duplicate merged: {:?}", job.announcement);
+                    self.pending.fetch_sub(1, Ordering::SeqCst);
+                    return;
+                }
+                // Urgent messages go behind other urgent ones, calls ahead of annonces.
+                let ahead_of = |queued: &Job| match &job.announcement {
+                    a if a.is_urgent() => !queued.announcement.is_urgent(),
+                    a if a.is_background() => false,
+                    _ => queued.announcement.is_background(),
+                };
+                let position = self
+                    .queue
+                    .iter()
+                    .position(ahead_of)
+                    .unwrap_or(self.queue.len());
+                self.queue.insert(position, job);
+            }
+        }
+    }
+
+    fn drain(&mut self) {
+        while let Ok(command) = self.rx.try_recv() {
+            self.handle(command);
+        }
+    }
+
+    // Chime first, then the call once per configured language, in order. Everything
+    // is waited for, so announcements never cut each other off.
+    fn play(&mut self, job: &Job, audio: Option<&AudioOutput>) {
+        let sounds = job.sounds.as_ref().unwrap_or(&self.sounds).clone();
+        let settings = job.tts.as_ref().unwrap_or(&self.settings).clone();
+
+        self.speaking.store(true, Ordering::SeqCst);
+        if let Some(audio) = audio {
+            audio.play_event(&sounds, job.announcement.sound());
+        }
+        if settings.enabled {
+            self.speak(&job.announcement, &settings, audio);
+        }
+        self.speaking.store(false, Ordering::SeqCst);
+    }
+
+    fn speak(&mut self, announcement: &Announcement, settings: &TtsSettings, audio: Option<&AudioOutput>) {
+        // Taken out for the duration so the queue can keep draining meanwhile.
+        let mut tts = self.tts.take();
+        if let Some(tts) = tts.as_mut() {
+            apply_prosody(tts, settings);
+        }
+
+        // Free text is written in one language: only the first voice reads it.
+        let languages = match announcement {
+            Announcement::Message { .. } | Announcement::Annonce { .. } => {
+                &settings.languages[..settings.languages.len().min(1)]
+            }
+            _ => &settings.languages[..],
+        };
+        for entry in languages {
+            let Some(text) = entry.render(announcement) else {
+                continue;
+            };
+            println!("🔊 [{}] {}", entry.language, text);
+            if let Some(tts) = tts.as_mut() {
+                apply_voice(tts, entry);
+            }
+
+            // Without an output device the recorded clips cannot play: TTS reads it all.
+            let pack = entry.voice_pack.as_deref().and_then(|id| self.packs.get(id));
+            let segments = match (pack, audio) {
+                (Some(pack), Some(_)) => pack.plan(&text),
+                _ => vec![Segment::Speech(text)],
+            };
+
+            // Consecutive clips are queued together so they play without gaps.
+            let mut clips = Vec::new();
+            for segment in segments {
+                match segment {
+                    Segment::Clip(path) => clips.push(path),
+                    Segment::Speech(words) => {
+                        if let Some(audio) = audio {
+                            audio.play_files(&clips, settings.volume);
+                        }
+                        clips.clear();
+                        match tts.as_mut() {
+                            Some(tts) => self.say(tts, &words),
+                            None => eprintln!("⚠️ No TTS engine to read \"{}\"", words),
+                        }
+                    }
+                }
+            }
+            if let Some(audio) = audio {
+                audio.play_files(&clips, settings.volume);
+            }
+        }
+
+        self.tts = tts;
+    }
+
+    fn say(&mut self, tts: &mut Tts, text: &str) {
+        if let Err(e) = tts.speak(text, false) {
+            eprintln!("❌ TTS error: {}", e);
+            return;
+        }
+
+        if tts.supported_features().is_speaking {
+            // Give the engine a moment to start before polling.
+            thread::sleep(POLL_INTERVAL);
+            while tts.is_speaking().unwrap_or(false) {
+                thread::sleep(POLL_INTERVAL);
+                self.drain();
+            }
+        } else {
+            thread::sleep(Duration::from_millis(
+                FALLBACK_MS_PER_CHAR * text.chars().count() as u64,
+            ));
+            self.drain();
+        }
+    }
+}
+
+// "fr" matches "fr-FR" and the other way around.
+fn language_matches(tag: &str, wanted: &str) -> bool {
+    let primary = |t: &str| t.split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+    tag.eq_ignore_ascii_case(wanted) || primary(tag) == primary(wanted)
+}
+
+// Failures are ignored on purpose: an engine that refuses a voice or a rate still
+// speaks with its defaults, which beats staying silent.
+fn apply_voice(tts: &mut Tts, entry: &LanguageAnnouncement) {
+    if !tts.supported_features().voice {
+        return;
+    }
+    let Ok(voices) = tts.voices() else {
+        return;
+    };
+
+    let voice = match &entry.voice_id {
+        Some(id) => voices.iter().find(|v| &v.id() == id),
+        None => voices
+            .iter()
+            .find(|v| v.language().as_str().eq_ignore_ascii_case(&entry.language))
+            .or_else(|| {
+                voices
+                    .iter()
+                    .find(|v| language_matches(v.language().as_str(), &entry.language))
+            }),
+    };
+    if let Some(voice) = voice {
+        let _ = tts.set_voice(voice);
+    }
+}
+
+fn apply_prosody(tts: &mut Tts, settings: &TtsSettings) {
+    let features = tts.supported_features();
+
+    if features.rate {
+        let rate = (tts.normal_rate() * settings.rate).clamp(tts.min_rate(), tts.max_rate());
+        let _ = tts.set_rate(rate);
+    }
+    if features.pitch {
+        let pitch = (tts.normal_pitch() * settings.pitch).clamp(tts.min_pitch(), tts.max_pitch());
+        let _ = tts.set_pitch(pitch);
+    }
+    if features.volume {
+        let volume = tts.min_volume() + (tts.max_volume() - tts.min_volume()) * settings.volume;
+        let _ = tts.set_volume(volume);
+    }
+}
@@ -0,0 +1,215 @@
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use crate::error::{QmsError, QmsResult};
+
+// Played when no custom chime is configured.
+const DEFAULT_CHIME: &[u8] = include_bytes!("../assets/chime.wav");
+
+const SUPPORTED_EXTENSIONS: [&str; 2] = ["wav", "mp3"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundEvent {
+    Call,
+    Recall,
+    Urgent,
+}
+
+// Persisted under `settings::SOUNDS`. Independent of the TTS settings so a site can
+// keep the chime with speech disabled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SoundSettings {
+    pub chime_enabled: bool,
+    // WAV/MP3 path; `None` uses the bundled chime.
+    pub chime_file: Option<String>,
+    // Per-event overrides; `None` falls back to the chime.
+    pub recall_file: Option<String>,
+    pub urgent_file: Option<String>,
+    // 0.0 (silent) to 1.0 (file level)
+    pub volume: f32,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        SoundSettings {
+            chime_enabled: true,
+            chime_file: None,
+            recall_file: None,
+            urgent_file: None,
+            volume: 1.0,
+        }
+    }
+}
+
+impl SoundSettings {
+    pub fn validate(&self) -> QmsResult<()> {
+        if !(0.0..=1.0).contains(&self.volume) {
+            return Err(QmsError::Validation("Sound volume must be between 0 and 1".to_string()));
+        }
+        for file in [&self.chime_file, &self.recall_file, &self.urgent_file]
+            .into_iter()
+            .flatten()
+        {
+            let path = Path::new(file);
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_ascii_lowercase())
+                .unwrap_or_default();
+            if !SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
+                return Err(QmsError::Validation(format!(
+                    "Unsupported audio file (WAV or MP3 expected): {}",
+                    file
+                )));
+            }
+            if !path.is_file() {
+                return Err(QmsError::NotFound(format!("Audio file not found: {}", file)));
+            }
+        }
+        Ok(())
+    }
+
+    fn file_for(&self, event: SoundEvent) -> Option<&str> {
+        let specific = match event {
+            SoundEvent::Call => None,
+            SoundEvent::Recall => self.recall_file.as_deref(),
+            SoundEvent::Urgent => self.urgent_file.as_deref(),
+        };
+        specific.or(self.chime_file.as_deref())
+    }
+
+    // Raw bytes of the event sound, for rendering it into clips. Same fallback as
+    // `AudioOutput::play_event`.
+    pub fn chime_data(&self, event: SoundEvent) -> Option<Vec<u8>> {
+        if !self.chime_enabled {
+            return None;
+        }
+        if let Some(path) = self.file_for(event) {
+            match std::fs::read(path) {
+                Ok(data) => return Some(data),
+                Err(e) => eprintln!("❌ Cannot open {}: {}", path, e),
+            }
+        }
+        Some(DEFAULT_CHIME.to_vec())
+    }
+}
+
+// Decodes a WAV/MP3 file to mono 16-bit samples at `rate`, whatever its own format,
+// so clips from different sources can be joined end to end.
+pub fn decode_mono(data: Vec<u8>, rate: u32) -> QmsResult<Vec<i16>> {
+    let decoder = Decoder::new(Cursor::new(data))
+        .map_err(|e| QmsError::Internal(format!("Cannot decode audio: {}", e)))?;
+    let channels = decoder.channels().max(1) as usize;
+    let source_rate = decoder.sample_rate().max(1);
+
+    let interleaved: Vec<i16> = decoder.collect();
+    let mono: Vec<i16> = interleaved
+        .chunks(channels)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect();
+
+    if source_rate == rate || mono.is_empty() {
+        return Ok(mono);
+    }
+    // Linear interpolation is plenty for speech and chimes.
+    let step = source_rate as f64 / rate as f64;
+    let length = (mono.len() as f64 / step) as usize;
+    Ok((0..length)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let next = mono[(index + 1).min(mono.len() - 1)] as f64;
+            let current = mono[index] as f64;
+            (current + (next - current) * (position - index as f64)) as i16
+        })
+        .collect())
+}
+
+// Owns the output device. The cpal stream is not `Send`, so this is created and used
+// on the announcer thread only.
+pub struct AudioOutput {
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+}
+
+impl AudioOutput {
+    pub fn open() -> Option<Self> {
+        match OutputStream::try_default() {
+            Ok((stream, handle)) => Some(AudioOutput {
+                _stream: stream,
+                handle,
+            }),
+            Err(e) => {
+                eprintln!("❌ No audio output, chimes disabled: {}", e);
+                None
+            }
+        }
+    }
+
+    // Blocks until the sound is over so speech never overlaps it. A custom file that
+    // cannot be read falls back to the bundled chime rather than to silence.
+    pub fn play_event(&self, settings: &SoundSettings, event: SoundEvent) {
+        if !settings.chime_enabled {
+            return;
+        }
+
+        if let Some(path) = settings.file_for(event) {
+            match File::open(path) {
+                Ok(file) => match self.play(BufReader::new(file), settings.volume) {
+                    Ok(()) => return,
+                    Err(e) => eprintln!("❌ Cannot play {}: {}", path, e),
+                },
+                Err(e) => eprintln!("❌ Cannot open {}: {}", path, e),
+            }
+        }
+
+        if let Err(e) = self.play(Cursor::new(DEFAULT_CHIME), settings.volume) {
+            eprintln!("❌ Cannot play default chime: {}", e);
+        }
+    }
+
+    // Plays recorded clips back to back, waiting for the last one. Unreadable clips
+    // are skipped.
+    pub fn play_files(&self, paths: &[PathBuf], volume: f32) {
+        if paths.is_empty() {
+            return;
+        }
+        let sink = match Sink::try_new(&self.handle) {
+            Ok(sink) => sink,
+            Err(e) => {
+                eprintln!("❌ Cannot play clips: {}", e);
+                return;
+            }
+        };
+        sink.set_volume(volume);
+        for path in paths {
+            let decoded = File::open(path)
+                .map_err(|e| e.to_string())
+                .and_then(|file| Decoder::new(BufReader::new(file)).map_err(|e| e.to_string()));
+            match decoded {
+                Ok(source) => sink.append(source),
+                Err(e) => eprintln!("❌ Cannot play {}: {}", path.display(), e),
+            }
+        }
+        sink.sleep_until_end();
+    }
+
+    fn play<R>(&self, data: R, volume: f32) -> Result<(), Box<dyn std::error::Error>>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        let source = Decoder::new(data)?;
+        let sink = Sink::try_new(&self.handle)?;
+        sink.set_volume(volume);
+        sink.append(source);
+        sink.sleep_until_end();
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+// Who changed what, and when. Every command or endpoint that changes something
+// appends an entry; the table itself refuses updates and deletes (migration 13).
+use rusqlite::{params, types::Value as SqlValue, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::auth::Session;
+use crate::error::{QmsError, QmsResult};
+use crate::export::{ExportFormat, ExportWriter};
+use crate::schedule;
+use crate::{Database, Device};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+// Who did it and from where.
+#[derive(Clone, Debug)]
+pub struct Actor {
+    name: Option<String>,
+    staff_id: Option<i32>,
+    source: String,
+}
+
+impl Actor {
+    // Someone logged in to the admin app.
+    pub fn staff(session: &Session) -> Self {
+        Actor {
+            name: Some(session.name.clone()),
+            staff_id: Some(session.staff_id),
+            source: "admin app".to_string(),
+        }
+    }
+
+    // A button, screen or kiosk, over HTTP.
+    pub fn device(device: &Device, ip: IpAddr) -> Self {
+        Actor {
+            name: Some(device.name.clone()),
+            staff_id: None,
+            source: ip.to_string(),
+        }
+    }
+
+    // Not logged in (yet), e.g. a failed login.
+    pub fn anonymous(source: impl Into<String>) -> Self {
+        Actor {
+            name: None,
+            staff_id: None,
+            source: source.into(),
+        }
+    }
+
+    pub fn audit<'a>(&'a self, action: &'a str) -> Audit<'a> {
+        Audit { actor: self, action }
+    }
+}
+
+// The entry for a change, handed to the method making it. That method records it in
+// its own transaction, so the change and its entry are committed together or not at all.
+pub struct Audit<'a> {
+    actor: &'a Actor,
+    action: &'a str,
+}
+
+impl Audit<'_> {
+    pub fn record(&self, conn: &Connection, before: Option<Value>, after: Option<Value>) -> QmsResult<()> {
+        conn.execute(
+            "INSERT INTO audit_log (action, actor, staff_id, source, before_value, after_value)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.action,
+                self.actor.name,
+                self.actor.staff_id,
+                self.actor.source,
+                before.map(|v| v.to_string()),
+                after.map(|v| v.to_string())
+            ],
+        )
+        .map_err(|e| {
+            eprintln!("❌ Cannot write audit entry '{}': {}", self.action, e);
+            QmsError::from(e)
+        })?;
+        println!("📝 Audit: {} by {}", self.action, self.actor.name.as_deref().unwrap_or(&self.actor.source));
+        Ok(())
+    }
+}
+
+// For `before` / `after`. Never fails: a value that cannot be serialised is left out.
+pub fn value<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AuditEntry {
+    id: i64,
+    action: String,
+    actor: Option<String>,
+    staff_id: Option<i32>,
+    source: Option<String>,
+    before: Option<Value>,
+    after: Option<Value>,
+    // Local time, "YYYY-MM-DD HH:MM:SS"
+    created_at: String,
+}
+
+const AUDIT_COLUMNS: &[&str] = &["id", "created_at", "action", "actor", "staff_id", "source", "before", "after"];
+
+impl AuditEntry {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let json = |text: Option<String>| text.and_then(|t| serde_json::from_str(&t).ok());
+        Ok(AuditEntry {
+            id: row.get(0)?,
+            action: row.get(1)?,
+            actor: row.get(2)?,
+            staff_id: row.get(3)?,
+            source: row.get(4)?,
+            before: json(row.get(5)?),
+            after: json(row.get(6)?),
+            created_at: row.get(7)?,
+        })
+    }
+}
+
+const ENTRY_SELECT: &str = "SELECT id, action, actor, staff_id, source, before_value, after_value,
+                                   datetime(created_at, 'localtime')";
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AuditQuery {
+    // "YYYY-MM-DD", both inclusive, local dates.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub action: Option<String>,
+    // Part of the staff member's or device's name.
+    pub actor: Option<String>,
+    pub staff_id: Option<i32>,
+    pub offset: u32,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuditPage {
+    items: Vec<AuditEntry>,
+    total: i64,
+    offset: u32,
+    limit: u32,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> QmsResult<(String, Vec<SqlValue>)> {
+        let from = schedule::parse_date(&self.from)?;
+        let to = schedule::parse_date(&self.to)?;
+        if let (Some(from), Some(to)) = (from, to) {
+            if to < from {
+                return Err(QmsError::Validation("End date is before start date".to_string()));
+            }
+        }
+
+        let mut sql = "FROM audit_log WHERE 1 = 1".to_string();
+        let mut values: Vec<SqlValue> = Vec::new();
+        if let Some(from) = from {
+            sql.push_str(" AND date(created_at, 'localtime') >= ?");
+            values.push(SqlValue::Text(from.to_string()));
+        }
+        if let Some(to) = to {
+            sql.push_str(" AND date(created_at, 'localtime') <= ?");
+            values.push(SqlValue::Text(to.to_string()));
+        }
+        if let Some(action) = self.action.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+            sql.push_str(" AND action = ?");
+            values.push(SqlValue::Text(action.to_string()));
+        }
+        if let Some(actor) = self.actor.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+            sql.push_str(" AND actor LIKE ? ESCAPE '\\'");
+            let escaped = actor.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            values.push(SqlValue::Text(format!("%{}%", escaped)));
+        }
+        if let Some(staff_id) = self.staff_id {
+            sql.push_str(" AND staff_id = ?");
+            values.push(SqlValue::Integer(staff_id as i64));
+        }
+        Ok((sql, values))
+    }
+}
+
+impl Database {
+    // For events that change nothing else: logins, exports, announcements made.
+    pub fn audit(&self, actor: &Actor, action: &str, before: Option<Value>, after: Option<Value>) -> QmsResult<()> {
+        actor.audit(action).record(&self.writer(), before, after)
+    }
+
+    // Newest first.
+    pub fn query_audit_log(&self, query: &AuditQuery) -> QmsResult<AuditPage> {
+        let (filter, values) = query.filter()?;
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let conn = self.reader()?;
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) {}", filter),
+            rusqlite::params_from_iter(&values),
+            |row| row.get(0),
+        )?;
+        let mut stmt = conn.prepare(&format!(
+            "{} {} ORDER BY id DESC LIMIT {} OFFSET {}",
+            ENTRY_SELECT, filter, limit, query.offset
+        ))?;
+        let iter = stmt.query_map(rusqlite::params_from_iter(&values), AuditEntry::from_row)?;
+        let items = iter.collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AuditPage {
+            items,
+            total,
+            offset: query.offset,
+            limit,
+        })
+    }
+
+    // Every matching entry, oldest first; paging is ignored. Returns the number written.
+    pub fn export_audit_log(&self, query: &AuditQuery, path: &Path, format: ExportFormat) -> QmsResult<u64> {
+        let (filter, values) = query.filter()?;
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!("{} {} ORDER BY id", ENTRY_SELECT, filter))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(&values))?;
+
+        let mut writer = ExportWriter::create(path, format, "Audit log", AUDIT_COLUMNS)?;
+        while let Some(row) = rows.next()? {
+            writer.write(&AuditEntry::from_row(row)?)?;
+        }
+        writer.finish()
+    }
+}
@@ -0,0 +1,262 @@
+// Who is using the admin app. Staff accounts carry a role; logging in with their
+// password (or PIN) opens a session that sensitive commands are checked against.
+// Commands the display page needs stay open so a locked PC still shows the screen.
+use chrono::Local;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::audit::{self, Actor};
+use crate::error::{QmsError, QmsResult};
+use crate::staff::{hash_secret, verify_secret};
+use crate::Database;
+
+// Locks the app after this long without a checked command.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const MIN_PASSWORD_LEN: usize = 8;
+// Failed logins allowed before a lockout; each further failure doubles it.
+const FREE_ATTEMPTS: u32 = 5;
+const FIRST_LOCKOUT: Duration = Duration::from_secs(30);
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+// Ordered: each role can do everything the ones before it can.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Operator,
+    Supervisor,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Operator => "operator",
+            Role::Supervisor => "supervisor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn from_column(role: &str) -> Role {
+        match role {
+            "admin" => Role::Admin,
+            "supervisor" => Role::Supervisor,
+            _ => Role::Operator,
+        }
+    }
+}
+
+pub fn validate_password(password: &str) -> QmsResult<()> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(QmsError::Validation(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Session {
+    pub staff_id: i32,
+    pub name: String,
+    pub role: Role,
+    // Local time, "YYYY-MM-DD HH:MM:SS"
+    pub started_at: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuthStatus {
+    session: Option<Session>,
+    // No admin account exists yet: the app asks for one before anything else.
+    needs_setup: bool,
+}
+
+struct Active {
+    session: Session,
+    last_used: Instant,
+}
+
+// Kept in memory only: restarting the app locks it.
+#[derive(Clone, Default)]
+pub struct AuthState(Arc<RwLock<Option<Active>>>);
+
+impl AuthState {
+    pub fn current(&self) -> Option<Session> {
+        let active = self.0.read().unwrap_or_else(|e| e.into_inner());
+        active
+            .as_ref()
+            .filter(|a| a.last_used.elapsed() < IDLE_TIMEOUT)
+            .map(|a| a.session.clone())
+    }
+
+    pub fn set(&self, session: Option<Session>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = session.map(|session| Active {
+            session,
+            last_used: Instant::now(),
+        });
+    }
+
+    // The logged-in session if its role is at least `role`. Also keeps it alive.
+    pub fn require(&self, role: Role) -> QmsResult<Session> {
+        let mut active = self.0.write().unwrap_or_else(|e| e.into_inner());
+        let Some(current) = active.as_mut() else {
+            return Err(QmsError::Unauthorized("Please log in".to_string()));
+        };
+        if current.last_used.elapsed() >= IDLE_TIMEOUT {
+            *active = None;
+            return Err(QmsError::Unauthorized("Session expired, please log in again".to_string()));
+        }
+        if current.session.role < role {
+            return Err(QmsError::Forbidden(format!(
+                "This needs the {} role",
+                role.as_str()
+            )));
+        }
+        current.last_used = Instant::now();
+        Ok(current.session.clone())
+    }
+}
+
+struct Failures {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+// Failed logins per account name or per device address, so a PIN cannot be guessed
+// by trying them all. Kept in memory: a restart forgets them.
+#[derive(Clone, Default)]
+pub struct LoginThrottle(Arc<Mutex<HashMap<String, Failures>>>);
+
+impl LoginThrottle {
+    // Refuses while `key` is locked out.
+    pub fn check(&self, key: &str) -> QmsResult<()> {
+        let failures = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        match failures.get(key).and_then(|f| f.locked_until).filter(|until| *until > now) {
+            Some(until) => Err(QmsError::Locked(format!(
+                "Too many failed attempts, try again in {} s",
+                (until - now).as_secs() + 1
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn failed(&self, key: &str) {
+        let mut failures = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = failures.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            locked_until: None,
+        });
+        entry.count += 1;
+        if entry.count >= FREE_ATTEMPTS {
+            let doublings = (entry.count - FREE_ATTEMPTS).min(16);
+            let lockout = (FIRST_LOCKOUT * 2u32.pow(doublings)).min(MAX_LOCKOUT);
+            entry.locked_until = Some(Instant::now() + lockout);
+            eprintln!("⚠️ {} failed logins for {}, locked for {:?}", entry.count, key, lockout);
+        }
+    }
+
+    pub fn succeeded(&self, key: &str) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+    }
+}
+
+impl Database {
+    pub fn admin_exists(&self) -> QmsResult<bool> {
+        let conn = self.reader()?;
+        let exists = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM staff WHERE role = 'admin' AND active = 1)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    pub fn auth_status(&self, session: Option<Session>) -> QmsResult<AuthStatus> {
+        Ok(AuthStatus {
+            session,
+            needs_setup: !self.admin_exists()?,
+        })
+    }
+
+    // `name` is matched without case; `secret` is the password, or the PIN for
+    // operators only (a few digits are no protection for an admin account). Failures
+    // do not say which part was wrong.
+    pub fn authenticate(&self, name: &str, secret: &str) -> QmsResult<Session> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, role, password_hash, pin_hash FROM staff
+             WHERE active = 1 AND name = ?1 COLLATE NOCASE",
+        )?;
+        let candidates = stmt
+            .query_map(params![name.trim()], |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (staff_id, name, role, password_hash, pin_hash) in candidates {
+            let role = Role::from_column(&role);
+            let pin_hash = pin_hash.filter(|_| role == Role::Operator);
+            let matches = [password_hash, pin_hash]
+                .iter()
+                .flatten()
+                .any(|hash| verify_secret(secret, hash));
+            if matches {
+                return Ok(Session {
+                    staff_id,
+                    name,
+                    role,
+                    started_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                });
+            }
+        }
+        Err(QmsError::Unauthorized("Invalid name or password".to_string()))
+    }
+
+    // First start: creates the admin account. Refused once an admin exists.
+    pub fn create_first_admin(&self, name: &str, password: &str) -> QmsResult<Session> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(QmsError::Validation("Staff name cannot be empty".to_string()));
+        }
+        validate_password(password)?;
+        let hash = hash_secret(password)?;
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM staff WHERE role = 'admin' AND active = 1)",
+            [],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(QmsError::Forbidden("An admin account already exists".to_string()));
+        }
+        tx.execute(
+            "INSERT INTO staff (name, role, password_hash) VALUES (?1, 'admin', ?2)",
+            params![name, hash],
+        )?;
+        let session = Session {
+            staff_id: tx.last_insert_rowid() as i32,
+            name: name.to_string(),
+            role: Role::Admin,
+            started_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        // Recorded as done by the account it creates.
+        Actor::staff(&session).audit("admin_created").record(&tx, None, audit::value(&session))?;
+        tx.commit()?;
+
+        Ok(session)
+    }
+}
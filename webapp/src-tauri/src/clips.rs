@@ -0,0 +1,343 @@
+// Renders calls to WAV clips that remote screens download and play themselves, since
+// only the PC running the app has speakers. Speech comes from the platform's command
+// line engine (espeak-ng, `say`, System.Speech), independent of the live announcer,
+// or from the language's voice pack when it has one.
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use uuid::Uuid;
+
+use crate::announcer::{Announcement, LanguageAnnouncement, TtsSettings};
+use crate::audio::{self, SoundSettings};
+use crate::error::{QmsError, QmsResult};
+use crate::voicepack::{Segment, VoicePacks};
+
+// Every clip is written in this format: small, and played by any browser.
+pub const CLIP_RATE: u32 = 22050;
+// Silence between the chime and each language.
+const GAP_MS: u32 = 400;
+// Oldest clips are deleted beyond this count.
+const MAX_CLIPS: usize = 200;
+
+pub struct ClipStore {
+    dir: PathBuf,
+    packs: VoicePacks,
+}
+
+impl ClipStore {
+    // Clips are only useful for the call being shown, so leftovers are cleared.
+    pub fn open(dir: PathBuf, packs: VoicePacks) -> QmsResult<Self> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| QmsError::Internal(format!("Cannot create {}: {}", dir.display(), e)))?;
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+        Ok(ClipStore { dir, packs })
+    }
+
+    // Returns the clip's file name, or `None` when there is nothing to play (speech and
+    // chime both disabled). The same call with the same settings reuses its clip, so a
+    // recall is not rendered twice.
+    pub fn render(
+        &self,
+        announcement: &Announcement,
+        tts: &TtsSettings,
+        sounds: &SoundSettings,
+    ) -> QmsResult<Option<String>> {
+        let phrases: Vec<(&LanguageAnnouncement, String)> = if tts.enabled {
+            tts.languages
+                .iter()
+                .filter_map(|entry| entry.render(announcement).map(|text| (entry, text)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let chime = sounds.chime_data(announcement.sound());
+        if phrases.is_empty() && chime.is_none() {
+            return Ok(None);
+        }
+
+        let name = clip_name(&phrases, tts, sounds, announcement);
+        // A pack reloaded with new recordings must not serve the old clip.
+        let name = match phrases.iter().any(|(entry, _)| entry.voice_pack.is_some()) {
+            true => format!("p{}-{}", self.packs.generation(), name),
+            false => name,
+        };
+        let path = self.dir.join(&name);
+        if path.is_file() {
+            return Ok(Some(name));
+        }
+
+        let mut segments = Vec::new();
+        if let Some(chime) = chime {
+            segments.push(audio::decode_mono(chime, CLIP_RATE)?);
+        }
+        for (entry, text) in &phrases {
+            let parts = match entry.voice_pack.as_deref().and_then(|id| self.packs.get(id)) {
+                Some(pack) => pack.plan(text),
+                None => vec![Segment::Speech(text.clone())],
+            };
+
+            let mut samples = Vec::new();
+            for part in parts {
+                let result = match &part {
+                    Segment::Clip(path) => read_file(path).and_then(|data| audio::decode_mono(data, CLIP_RATE)),
+                    Segment::Speech(words) => {
+                        // Unique: the same clip may be rendered twice at once (call, then recall).
+                        let speech = self.dir.join(format!("{}.{}.tmp.wav", name, Uuid::new_v4().simple()));
+                        let result = synthesize(words, entry, tts, &speech)
+                            .and_then(|()| read_file(&speech))
+                            .and_then(|data| audio::decode_mono(data, CLIP_RATE));
+                        let _ = fs::remove_file(&speech);
+                        result
+                    }
+                };
+                // One part failing (e.g. no voice installed) should not cost the rest.
+                match result {
+                    Ok(part) => samples.extend(part),
+                    Err(e) => eprintln!("❌ Cannot render [{}] {:?}: {}", entry.language, part, e),
+                }
+            }
+            if !samples.is_empty() {
+                segments.push(samples);
+            }
+        }
+
+        write_clip(&path, &segments)?;
+        self.prune();
+        Ok(Some(name))
+    }
+
+    // Resolves a requested file name, refusing anything that could leave the folder.
+    pub fn path_of(&self, name: &str) -> Option<PathBuf> {
+        let valid = name.ends_with(".wav")
+            && !name.contains(".tmp.")
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !valid || name.starts_with('.') {
+            return None;
+        }
+        let path = self.dir.join(name);
+        path.is_file().then_some(path)
+    }
+
+    // Files still being written by another render are left alone.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut clips: Vec<_> = entries
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().contains(".tmp."))
+            .filter_map(|entry| {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect();
+        if clips.len() <= MAX_CLIPS {
+            return;
+        }
+        clips.sort();
+        for (_, path) in &clips[..clips.len() - MAX_CLIPS] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn clip_name(
+    phrases: &[(&LanguageAnnouncement, String)],
+    tts: &TtsSettings,
+    sounds: &SoundSettings,
+    announcement: &Announcement,
+) -> String {
+    let mut hasher = DefaultHasher::new();
+    for (entry, text) in phrases {
+        entry.language.hash(&mut hasher);
+        entry.voice_id.hash(&mut hasher);
+        entry.voice_pack.hash(&mut hasher);
+        text.hash(&mut hasher);
+    }
+    for value in [tts.rate, tts.pitch, tts.volume, sounds.volume] {
+        value.to_bits().hash(&mut hasher);
+    }
+    sounds.chime_enabled.hash(&mut hasher);
+    sounds.chime_file.hash(&mut hasher);
+    sounds.recall_file.hash(&mut hasher);
+    sounds.urgent_file.hash(&mut hasher);
+    announcement.sound().hash(&mut hasher);
+    format!("{:016x}.wav", hasher.finish())
+}
+
+fn read_file(path: &Path) -> QmsResult<Vec<u8>> {
+    fs::read(path).map_err(|e| QmsError::Internal(format!("Cannot read {}: {}", path.display(), e)))
+}
+
+// Joins the segments with a short silence, written to a temporary file first so a
+// screen never downloads half a clip. Concurrent writers of the same clip each use
+// their own temporary file; the last rename wins, with identical content.
+fn write_clip(path: &Path, segments: &[Vec<i16>]) -> QmsResult<()> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: CLIP_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let gap = (CLIP_RATE * GAP_MS / 1000) as usize;
+    let partial = path.with_extension(format!("{}.tmp.wav", Uuid::new_v4().simple()));
+
+    let write = || -> Result<(), hound::Error> {
+        let mut writer = hound::WavWriter::create(&partial, spec)?;
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 {
+                for _ in 0..gap {
+                    writer.write_sample(0i16)?;
+                }
+            }
+            for &sample in segment {
+                writer.write_sample(sample)?;
+            }
+        }
+        writer.finalize()
+    };
+    write().map_err(|e| QmsError::Internal(format!("Cannot write clip: {}", e)))?;
+    fs::rename(&partial, path)
+        .map_err(|e| QmsError::Internal(format!("Cannot write clip: {}", e)))
+}
+
+// --- SPEECH ENGINES ---
+// Rate, pitch and volume follow the same multipliers as the live announcer.
+
+#[cfg(target_os = "linux")]
+fn synthesize(text: &str, entry: &LanguageAnnouncement, tts: &TtsSettings, out: &Path) -> QmsResult<()> {
+    let args = [
+        "-v".to_string(),
+        espeak_voice(&entry.language),
+        "-s".to_string(),
+        ((175.0 * tts.rate) as i32).clamp(80, 450).to_string(),
+        "-p".to_string(),
+        ((50.0 * tts.pitch) as i32).clamp(0, 99).to_string(),
+        "-a".to_string(),
+        ((100.0 * tts.volume) as i32).clamp(0, 200).to_string(),
+        "-w".to_string(),
+        out.display().to_string(),
+        // A message starting with "-" is still read out, not taken as an option.
+        "--".to_string(),
+        text.to_string(),
+    ];
+    // Older distributions only ship the original espeak, with the same options.
+    match run_engine(Command::new("espeak-ng").args(&args)) {
+        Err(QmsError::NotFound(_)) => run_engine(Command::new("espeak").args(&args)),
+        result => result,
+    }
+}
+
+// espeak has no Lingala voice; spelled-out Lingala reads well with French phonetics.
+#[cfg(target_os = "linux")]
+fn espeak_voice(language: &str) -> String {
+    let tag = language.replace('_', "-").to_ascii_lowercase();
+    match tag.split('-').next() {
+        Some("ln") => "fr".to_string(),
+        _ => tag,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn synthesize(text: &str, entry: &LanguageAnnouncement, tts: &TtsSettings, out: &Path) -> QmsResult<()> {
+    let rate = ((175.0 * tts.rate) as i32).clamp(80, 450).to_string();
+    let mut command = Command::new("say");
+    if let Some(voice) = say_voice(entry) {
+        command.args(["-v", voice.as_str()]);
+    }
+    run_engine(
+        command
+            .arg("-o")
+            .arg(out)
+            .arg(format!("--data-format=LEI16@{}", CLIP_RATE))
+            .args(["-r", rate.as_str()])
+            .arg(text),
+    )
+}
+
+// The chosen voice, else the first installed one for the language (French for
+// Lingala), else `say`'s default. Voice ids end with the name `say` expects:
+// "com.apple.voice.compact.fr-FR.Thomas".
+#[cfg(target_os = "macos")]
+fn say_voice(entry: &LanguageAnnouncement) -> Option<String> {
+    if let Some(id) = &entry.voice_id {
+        return id.rsplit('.').next().map(str::to_string);
+    }
+    let tag = entry.language.replace('-', "_").to_ascii_lowercase();
+    let tag = match tag.split('_').next() {
+        Some("ln") => "fr".to_string(),
+        _ => tag,
+    };
+
+    // `say -v ?` lists "Name   fr_FR    # sample", names may contain spaces.
+    let output = Command::new("say").args(["-v", "?"]).output().ok()?;
+    let voices: Vec<(String, String)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split('#').next()?.split_whitespace().collect();
+            let (locale, name) = words.split_last()?;
+            Some((name.join(" "), locale.to_ascii_lowercase()))
+        })
+        .collect();
+    voices
+        .iter()
+        .find(|(_, locale)| *locale == tag)
+        .or_else(|| voices.iter().find(|(_, locale)| locale.split('_').next() == tag.split('_').next()))
+        .map(|(name, _)| name.clone())
+}
+
+#[cfg(target_os = "windows")]
+fn synthesize(text: &str, entry: &LanguageAnnouncement, tts: &TtsSettings, out: &Path) -> QmsResult<()> {
+    // Values go through the environment so the text never needs escaping.
+    const SCRIPT: &str = "Add-Type -AssemblyName System.Speech; \
+        $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+        try { $s.SelectVoiceByHints('NotSet', 'NotSet', 0, [Globalization.CultureInfo]::new($env:QMS_CULTURE)) } catch {}; \
+        $f = New-Object System.Speech.AudioFormat.SpeechAudioFormatInfo([int]$env:QMS_RATE_HZ, 'Sixteen', 'Mono'); \
+        $s.SetOutputToWaveFile($env:QMS_OUT, $f); \
+        $s.Rate = [int]$env:QMS_RATE; $s.Volume = [int]$env:QMS_VOLUME; \
+        $s.Speak($env:QMS_TEXT); $s.Dispose()";
+    let culture = match entry.language.split(['-', '_']).next() {
+        Some("ln") => "fr-FR",
+        _ => entry.language.as_str(),
+    };
+    run_engine(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+            .env("QMS_CULTURE", culture)
+            .env("QMS_RATE_HZ", CLIP_RATE.to_string())
+            .env("QMS_OUT", out)
+            .env("QMS_RATE", (((tts.rate - 1.0) * 10.0).round() as i32).clamp(-10, 10).to_string())
+            .env("QMS_VOLUME", ((tts.volume * 100.0) as i32).clamp(0, 100).to_string())
+            .env("QMS_TEXT", text),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn synthesize(_text: &str, _entry: &LanguageAnnouncement, _tts: &TtsSettings, _out: &Path) -> QmsResult<()> {
+    Err(QmsError::NotFound("No speech engine for clips on this platform".to_string()))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+fn run_engine(command: &mut Command) -> QmsResult<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => QmsError::NotFound(format!("Speech engine '{}' is not installed", program)),
+        _ => QmsError::Internal(format!("Cannot run {}: {}", program, e)),
+    })?;
+    if !output.status.success() {
+        return Err(QmsError::Internal(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// Emergency broadcast: one message takes over every screen, `/next` is refused and
+// the announcer loops the alarm until an admin clears it.
+use chrono::Local;
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Emergency {
+    pub message: String,
+    // Local time, "YYYY-MM-DD HH:MM:SS"
+    pub started_at: String,
+}
+
+impl Emergency {
+    pub fn new(message: String) -> Self {
+        Emergency {
+            message,
+            started_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    }
+}
+
+// Shared by the HTTP handlers and the Tauri commands. Kept in memory only: an
+// emergency does not survive a restart of the app.
+#[derive(Clone, Default)]
+pub struct EmergencyState(Arc<RwLock<Option<Emergency>>>);
+
+impl EmergencyState {
+    pub fn current(&self) -> Option<Emergency> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    // Returns the emergency that was replaced or cleared, if any.
+    pub fn set(&self, emergency: Option<Emergency>) -> Option<Emergency> {
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+        std::mem::replace(&mut *current, emergency)
+    }
+}
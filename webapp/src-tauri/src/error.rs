@@ -0,0 +1,127 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+use crate::migrations::MigrationError;
+
+// Single error type shared by the Database, the axum handlers and the Tauri commands.
+#[derive(Debug)]
+pub enum QmsError {
+    Db(rusqlite::Error),
+    Migration(MigrationError),
+    Unauthorized(String),
+    // Logged in, but the role does not allow it.
+    Forbidden(String),
+    Validation(String),
+    NotFound(String),
+    // Refused while the system is in a mode that forbids it (e.g. an emergency).
+    Locked(String),
+    // A background task (e.g. a blocking DB call) panicked or was cancelled.
+    Internal(String),
+}
+
+pub type QmsResult<T> = Result<T, QmsError>;
+
+impl QmsError {
+    // Stable identifier the frontend and the ESP32 can switch on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QmsError::Db(_) => "database",
+            QmsError::Migration(_) => "migration",
+            QmsError::Unauthorized(_) => "unauthorized",
+            QmsError::Forbidden(_) => "forbidden",
+            QmsError::Validation(_) => "validation",
+            QmsError::NotFound(_) => "not_found",
+            QmsError::Locked(_) => "locked",
+            QmsError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            // A locked DB is transient: tell the client to retry.
+            QmsError::Db(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                ) =>
+            {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            QmsError::Db(_) | QmsError::Migration(_) | QmsError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            QmsError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            QmsError::Forbidden(_) => StatusCode::FORBIDDEN,
+            QmsError::Validation(_) => StatusCode::BAD_REQUEST,
+            QmsError::NotFound(_) => StatusCode::NOT_FOUND,
+            QmsError::Locked(_) => StatusCode::LOCKED,
+        }
+    }
+}
+
+impl fmt::Display for QmsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QmsError::Db(e) => write!(f, "database error: {}", e),
+            QmsError::Migration(e) => write!(f, "{}", e),
+            QmsError::Unauthorized(msg)
+            | QmsError::Forbidden(msg)
+            | QmsError::Validation(msg)
+            | QmsError::NotFound(msg)
+            | QmsError::Locked(msg)
+            | QmsError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for QmsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QmsError::Db(e) => Some(e),
+            QmsError::Migration(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for QmsError {
+    fn from(e: rusqlite::Error) -> Self {
+        QmsError::Db(e)
+    }
+}
+
+impl From<tokio::task::JoinError> for QmsError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        QmsError::Internal(format!("background task failed: {}", e))
+    }
+}
+
+impl From<MigrationError> for QmsError {
+    fn from(e: MigrationError) -> Self {
+        QmsError::Migration(e)
+    }
+}
+
+// Tauri commands reject with `{ kind, message }` instead of a bare string.
+impl Serialize for QmsError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("QmsError", 2)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+// axum handlers answer with the matching status code and the same JSON body.
+impl IntoResponse for QmsError {
+    fn into_response(self) -> Response {
+        if let QmsError::Db(_) | QmsError::Migration(_) | QmsError::Internal(_) = self {
+            eprintln!("❌ {}", self);
+        }
+        (self.status_code(), Json(self)).into_response()
+    }
+}
@@ -0,0 +1,65 @@
+// Everything pushed to the screens on `/events`. Calls and pings keep the plain,
+// unnamed `data:` lines older screens already parse; newer events are named so
+// browsers can subscribe to them with `addEventListener(<name>)`.
+use axum::response::sse::Event;
+use serde::Serialize;
+
+use crate::emergency::Emergency;
+use crate::playlist::Slide;
+use crate::{Annonce, EtatFile};
+
+#[derive(Clone, Debug)]
+pub enum ServerEvent {
+    Ping,
+    // `{guichet, compteur, ...}` as returned by `/next`
+    Call(serde_json::Value),
+    // The same payload plus `audio_url`, once the call's clip is rendered.
+    CallAudio(serde_json::Value),
+    AnnonceAdded(Annonce),
+    AnnonceUpdated(Annonce),
+    AnnonceToggled(Annonce),
+    AnnonceDeleted { id: i32 },
+    // The annonces on air, sent when the schedule changes what is shown.
+    Annonces(Vec<Annonce>),
+    // The slides to play while idle, sent when the playlist or its schedule changes.
+    Playlist(Vec<Slide>),
+    // Full-screen takeover until `EmergencyCleared`.
+    Emergency(Emergency),
+    EmergencyCleared,
+}
+
+// First event of every connection, so a screen that (re)connects does not wait for
+// the next call to have something to show.
+#[derive(Serialize, Debug)]
+pub struct Snapshot {
+    pub etat: EtatFile,
+    pub annonces: Vec<Annonce>,
+    pub playlist: Vec<Slide>,
+    pub emergency: Option<Emergency>,
+}
+
+impl ServerEvent {
+    pub fn to_sse(&self) -> Result<Event, axum::Error> {
+        match self {
+            ServerEvent::Ping => Ok(Event::default().data("PING")),
+            ServerEvent::Call(payload) => Ok(Event::default().data(payload.to_string())),
+            ServerEvent::CallAudio(payload) => named("call_audio", payload),
+            ServerEvent::AnnonceAdded(annonce) => named("annonce_added", annonce),
+            ServerEvent::AnnonceUpdated(annonce) => named("annonce_updated", annonce),
+            ServerEvent::AnnonceToggled(annonce) => named("annonce_toggled", annonce),
+            ServerEvent::AnnonceDeleted { id } => named("annonce_deleted", serde_json::json!({ "id": id })),
+            ServerEvent::Annonces(annonces) => named("annonces", annonces),
+            ServerEvent::Playlist(slides) => named("playlist", slides),
+            ServerEvent::Emergency(emergency) => named("emergency", emergency),
+            ServerEvent::EmergencyCleared => named("emergency_cleared", serde_json::json!({})),
+        }
+    }
+}
+
+pub fn snapshot(snapshot: &Snapshot) -> Result<Event, axum::Error> {
+    named("snapshot", snapshot)
+}
+
+fn named<T: Serialize>(name: &str, payload: T) -> Result<Event, axum::Error> {
+    Event::default().event(name).json_data(payload)
+}
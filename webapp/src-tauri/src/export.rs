@@ -0,0 +1,228 @@
+// Writes query results to a file, one row at a time, so exports never hold the
+// whole result in memory. Rows are any `Serialize` struct; `columns` decides which
+// of its fields are written, and in what order. Rows go to a temporary file next to
+// the target, renamed over it by `finish`: an export that fails half-way leaves the
+// target untouched.
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+use crate::error::{QmsError, QmsResult};
+
+// Excel's limit, header row included.
+const XLSX_MAX_ROWS: u64 = 1_048_576;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    // An array of objects.
+    Json,
+    // One sheet. Rows are flushed to a temporary file as they are written, then
+    // zipped into the workbook by `finish`.
+    Xlsx,
+}
+
+enum Sink {
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    Json { out: BufWriter<File>, first: bool },
+    Xlsx { workbook: Box<Workbook>, sheet: Box<Worksheet> },
+}
+
+// Deleted when dropped, unless `finish` renamed it to the target.
+struct PartialFile {
+    path: PathBuf,
+    kept: bool,
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.kept {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+pub struct ExportWriter {
+    path: PathBuf,
+    partial: PartialFile,
+    columns: &'static [&'static str],
+    sink: Sink,
+    rows: u64,
+}
+
+fn write_error(path: &Path, e: impl std::fmt::Display) -> QmsError {
+    QmsError::Internal(format!("Cannot write {}: {}", path.display(), e))
+}
+
+impl ExportWriter {
+    // Replaces the file if it exists, once finished. `sheet` names the XLSX worksheet.
+    pub fn create(
+        path: &Path,
+        format: ExportFormat,
+        sheet: &str,
+        columns: &'static [&'static str],
+    ) -> QmsResult<Self> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| QmsError::Validation(format!("{} is not a file path", path.display())))?;
+        let partial = PartialFile {
+            path: path.with_file_name(format!(".{}.{}.partial", file_name.to_string_lossy(), Uuid::new_v4().simple())),
+            kept: false,
+        };
+        let sink = match format {
+            ExportFormat::Csv | ExportFormat::Json => {
+                let file = File::create(&partial.path).map_err(|e| write_error(path, e))?;
+                let out = BufWriter::new(file);
+                match format {
+                    ExportFormat::Csv => {
+                        let mut writer = csv::Writer::from_writer(out);
+                        writer.write_record(columns).map_err(|e| write_error(path, e))?;
+                        Sink::Csv(Box::new(writer))
+                    }
+                    _ => Sink::Json { out, first: true },
+                }
+            }
+            ExportFormat::Xlsx => {
+                let mut workbook = Workbook::new();
+                let mut worksheet = workbook.new_worksheet_with_constant_memory();
+                worksheet.set_name(sheet).map_err(|e| write_error(path, e))?;
+                let bold = Format::new().set_bold();
+                for (col, column) in columns.iter().enumerate() {
+                    worksheet
+                        .write_string_with_format(0, col as u16, *column, &bold)
+                        .map_err(|e| write_error(path, e))?;
+                }
+                Sink::Xlsx {
+                    workbook: Box::new(workbook),
+                    sheet: Box::new(worksheet),
+                }
+            }
+        };
+        Ok(ExportWriter {
+            path: path.to_path_buf(),
+            partial,
+            columns,
+            sink,
+            rows: 0,
+        })
+    }
+
+    pub fn write<T: Serialize>(&mut self, row: &T) -> QmsResult<()> {
+        let value = serde_json::to_value(row).map_err(|e| write_error(&self.path, e))?;
+        let path = &self.path;
+        match &mut self.sink {
+            Sink::Csv(writer) => {
+                let cells = self.columns.iter().map(|column| cell_text(value.get(column)));
+                writer.write_record(cells).map_err(|e| write_error(path, e))?;
+            }
+            Sink::Json { out, first } => {
+                let mut object = serde_json::Map::new();
+                for column in self.columns {
+                    object.insert(column.to_string(), value.get(column).cloned().unwrap_or(Value::Null));
+                }
+                out.write_all(if *first { b"[\n" } else { b",\n" })
+                    .map_err(|e| write_error(path, e))?;
+                serde_json::to_writer(&mut *out, &object).map_err(|e| write_error(path, e))?;
+                *first = false;
+            }
+            Sink::Xlsx { sheet, .. } => {
+                if self.rows + 1 >= XLSX_MAX_ROWS {
+                    return Err(QmsError::Validation(
+                        "Too many rows for an XLSX file, export to CSV instead".to_string(),
+                    ));
+                }
+                let row_num = (self.rows + 1) as u32;
+                for (col, column) in self.columns.iter().enumerate() {
+                    let col = col as u16;
+                    let written = match value.get(column) {
+                        None | Some(Value::Null) => continue,
+                        Some(Value::Number(n)) => sheet.write_number(row_num, col, n.as_f64().unwrap_or_default()),
+                        Some(Value::Bool(b)) => sheet.write_boolean(row_num, col, *b),
+                        Some(Value::String(s)) => sheet.write_string(row_num, col, s),
+                        Some(other) => sheet.write_string(row_num, col, other.to_string()),
+                    };
+                    written.map_err(|e| write_error(path, e))?;
+                }
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    // Flushes the file, moves it to the target and returns how many rows were written.
+    pub fn finish(mut self) -> QmsResult<u64> {
+        let path = &self.path;
+        match self.sink {
+            Sink::Csv(mut writer) => writer.flush().map_err(|e| write_error(path, e))?,
+            Sink::Json { mut out, first } => {
+                out.write_all(if first { b"[]\n" } else { b"\n]\n" })
+                    .map_err(|e| write_error(path, e))?;
+                out.flush().map_err(|e| write_error(path, e))?;
+            }
+            Sink::Xlsx { mut workbook, sheet } => {
+                workbook.push_worksheet(*sheet);
+                workbook.save(&self.partial.path).map_err(|e| write_error(path, e))?;
+            }
+        }
+        fs::rename(&self.partial.path, path).map_err(|e| write_error(path, e))?;
+        self.partial.kept = true;
+        Ok(self.rows)
+    }
+}
+
+// Strings as they are, nested values as JSON, nothing for null.
+fn cell_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        id: i32,
+        name: &'static str,
+    }
+
+    const COLUMNS: &[&str] = &["id", "name"];
+
+    #[test]
+    fn unfinished_export_leaves_no_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.csv");
+        fs::write(&path, "previous export").unwrap();
+
+        let mut writer = ExportWriter::create(&path, ExportFormat::Csv, "Rows", COLUMNS).unwrap();
+        writer.write(&Row { id: 1, name: "A" }).unwrap();
+        drop(writer);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous export");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn finished_export_replaces_the_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.json");
+        fs::write(&path, "previous export").unwrap();
+
+        let mut writer = ExportWriter::create(&path, ExportFormat::Json, "Rows", COLUMNS).unwrap();
+        writer.write(&Row { id: 1, name: "A" }).unwrap();
+        assert_eq!(writer.finish().unwrap(), 1);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[\n{\"id\":1,\"name\":\"A\"}\n]\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
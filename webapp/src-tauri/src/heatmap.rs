@@ -0,0 +1,158 @@
+// Load per weekday and hour of day, for staffing. Rows are weekdays (0 = Monday ...
+// 6 = Sunday), columns hours 0-23, in local time.
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+use crate::error::QmsResult;
+use crate::stats::StatsQuery;
+use crate::Database;
+
+pub type Matrix = [[u32; 24]; 7];
+
+#[derive(Serialize, Debug)]
+pub struct Heatmap {
+    from: String,
+    to: String,
+    calls: Matrix,
+    // Tickets issued at the kiosk. Not split by desk or operator: filtering on
+    // either leaves it out.
+    arrivals: Option<Matrix>,
+    pub calls_by_hour: [u32; 24],
+    calls_by_weekday: [u32; 7],
+    // How many Mondays, Tuesdays... the period holds, to turn totals into averages.
+    weekday_days: [u32; 7],
+}
+
+impl Heatmap {
+    // Calls per occurrence of the weekday, so periods of different lengths compare.
+    fn calls_per_day(&self) -> [[f64; 24]; 7] {
+        let mut averages = [[0.0; 24]; 7];
+        for (day, hours) in self.calls.iter().enumerate() {
+            let days = self.weekday_days[day].max(1) as f64;
+            for (hour, count) in hours.iter().enumerate() {
+                averages[day][hour] = *count as f64 / days;
+            }
+        }
+        averages
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct HeatmapComparison {
+    first: Heatmap,
+    second: Heatmap,
+    // Second minus first, in average calls per day for each cell.
+    calls_change: [[f64; 24]; 7],
+}
+
+impl Database {
+    pub fn get_heatmap(&self, query: &StatsQuery) -> QmsResult<Heatmap> {
+        let (from, to) = query.range()?;
+        let conn = self.reader()?;
+
+        let desk = query.desk.as_deref().map(str::trim).filter(|d| !d.is_empty());
+        let service = query.service.as_deref().map(str::trim).filter(|s| !s.is_empty());
+        let (first_day, last_day) = (from.to_string(), to.to_string());
+
+        let calls = count_matrix(
+            &conn,
+            "historique",
+            "created_at",
+            "ticket_number >= 0
+             AND (?3 IS NULL OR desk_name = ?3)
+             AND (?4 IS NULL OR service = ?4)
+             AND (?5 IS NULL OR operator_id = ?5)",
+            rusqlite::params![first_day, last_day, desk, service, query.operator_id],
+        )?;
+        let arrivals = match desk.is_some() || query.operator_id.is_some() {
+            true => None,
+            false => Some(count_matrix(
+                &conn,
+                "issued_tickets",
+                "issued_at",
+                "(?3 IS NULL OR service = ?3)",
+                rusqlite::params![first_day, last_day, service],
+            )?),
+        };
+
+        let mut calls_by_hour = [0; 24];
+        let mut calls_by_weekday = [0; 7];
+        for (day, hours) in calls.iter().enumerate() {
+            for (hour, count) in hours.iter().enumerate() {
+                calls_by_hour[hour] += count;
+                calls_by_weekday[day] += count;
+            }
+        }
+
+        Ok(Heatmap {
+            from: from.to_string(),
+            to: to.to_string(),
+            calls,
+            arrivals,
+            calls_by_hour,
+            calls_by_weekday,
+            weekday_days: weekday_days(from, to),
+        })
+    }
+
+    pub fn compare_heatmaps(&self, first: &StatsQuery, second: &StatsQuery) -> QmsResult<HeatmapComparison> {
+        let first = self.get_heatmap(first)?;
+        let second = self.get_heatmap(second)?;
+
+        let (before, after) = (first.calls_per_day(), second.calls_per_day());
+        let mut calls_change = [[0.0; 24]; 7];
+        for day in 0..7 {
+            for hour in 0..24 {
+                calls_change[day][hour] = ((after[day][hour] - before[day][hour]) * 100.0).round() / 100.0;
+            }
+        }
+
+        Ok(HeatmapComparison {
+            first,
+            second,
+            calls_change,
+        })
+    }
+}
+
+// Rows of `table` per local weekday and hour of `column`. `filter` may use ?3 on;
+// ?1 and ?2 are the date range.
+fn count_matrix(
+    conn: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    filter: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> QmsResult<Matrix> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT CAST(strftime('%w', {column}, 'localtime') AS INTEGER),
+                CAST(strftime('%H', {column}, 'localtime') AS INTEGER),
+                COUNT(*)
+         FROM {table}
+         WHERE date({column}, 'localtime') BETWEEN ?1 AND ?2
+         AND {filter}
+         GROUP BY 1, 2"
+    ))?;
+    let iter = stmt.query_map(params, |row| {
+        Ok((row.get::<_, u32>(0)?, row.get::<_, usize>(1)?, row.get::<_, u32>(2)?))
+    })?;
+
+    let mut matrix: Matrix = [[0; 24]; 7];
+    for cell in iter {
+        let (sunday_first, hour, count) = cell?;
+        // SQLite counts from Sunday.
+        let day = ((sunday_first + 6) % 7) as usize;
+        if hour < 24 {
+            matrix[day][hour] += count;
+        }
+    }
+    Ok(matrix)
+}
+
+fn weekday_days(from: NaiveDate, to: NaiveDate) -> [u32; 7] {
+    let mut days = [0; 7];
+    for date in from.iter_days().take_while(|d| *d <= to) {
+        days[date.weekday().num_days_from_monday() as usize] += 1;
+    }
+    days
+}
@@ -0,0 +1,271 @@
+// Queries over `historique`. Marker rows (-1 desk closed, -2 reset) are never
+// returned, only real tickets.
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::{QmsError, QmsResult};
+use crate::export::{ExportFormat, ExportWriter};
+use crate::schedule;
+use crate::Database;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TicketStatus {
+    Called,
+    // Called again by its desk at least once.
+    Recalled,
+    // Marked done by its desk.
+    Served,
+    // Called, but nobody came.
+    NoShow,
+}
+
+impl TicketStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TicketStatus::Called => "called",
+            TicketStatus::Recalled => "recalled",
+            TicketStatus::Served => "served",
+            TicketStatus::NoShow => "no_show",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct HistoryItem {
+    id: i32,
+    ticket_number: i32,
+    desk_name: String,
+    // The desk's service when the ticket was called.
+    service: Option<String>,
+    // Who was logged in at the desk.
+    operator_id: Option<i32>,
+    operator_name: Option<String>,
+    status: String,
+    created_at: String,
+}
+
+// One ticket from issue to end, for the exports. Times are local.
+#[derive(Serialize, Debug)]
+struct TicketRecord {
+    id: i32,
+    ticket_number: i32,
+    desk_name: String,
+    service: Option<String>,
+    operator_id: Option<i32>,
+    operator_name: Option<String>,
+    status: String,
+    issued_at: Option<String>,
+    called_at: String,
+    ended_at: Option<String>,
+    // Issued -> called, and called -> ended; missing when either end is unknown.
+    wait_minutes: Option<f64>,
+    service_minutes: Option<f64>,
+}
+
+const TICKET_COLUMNS: &[&str] = &[
+    "id",
+    "ticket_number",
+    "desk_name",
+    "service",
+    "operator_id",
+    "operator_name",
+    "status",
+    "issued_at",
+    "called_at",
+    "ended_at",
+    "wait_minutes",
+    "service_minutes",
+];
+
+// Every filter is optional; an empty query returns the most recent tickets.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct HistoryQuery {
+    // "YYYY-MM-DD", both inclusive, local dates.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub desk: Option<String>,
+    pub service: Option<String>,
+    pub operator_id: Option<i32>,
+    pub status: Option<TicketStatus>,
+    // Matched against the ticket number, desk and service.
+    pub search: Option<String>,
+    // Only tickets called after the last counter reset.
+    pub since_reset: bool,
+    pub offset: u32,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HistoryPage {
+    items: Vec<HistoryItem>,
+    // Matching tickets across all pages.
+    total: i64,
+    offset: u32,
+    limit: u32,
+}
+
+impl HistoryPage {
+    pub fn into_items(self) -> Vec<HistoryItem> {
+        self.items
+    }
+}
+
+impl HistoryQuery {
+    // The display page's history: the 5 tickets before the one on screen.
+    pub fn display() -> Self {
+        HistoryQuery {
+            since_reset: true,
+            offset: 1,
+            limit: Some(5),
+            ..HistoryQuery::default()
+        }
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    // `FROM ... WHERE ...` shared by the page, its count and the exports. Binds
+    // positional parameters, in order.
+    pub fn filter(&self) -> QmsResult<(String, Vec<Value>)> {
+        let from = schedule::parse_date(&self.from)?;
+        let to = schedule::parse_date(&self.to)?;
+        if let (Some(from), Some(to)) = (from, to) {
+            if to < from {
+                return Err(QmsError::Validation("End date is before start date".to_string()));
+            }
+        }
+
+        let mut sql = "FROM historique WHERE ticket_number >= 0".to_string();
+        let mut values: Vec<Value> = Vec::new();
+
+        if self.since_reset {
+            sql.push_str(" AND id > (SELECT COALESCE(MAX(id), 0) FROM historique WHERE ticket_number = -2)");
+        }
+        // `created_at` is UTC.
+        if let Some(from) = from {
+            sql.push_str(" AND date(created_at, 'localtime') >= ?");
+            values.push(Value::Text(from.to_string()));
+        }
+        if let Some(to) = to {
+            sql.push_str(" AND date(created_at, 'localtime') <= ?");
+            values.push(Value::Text(to.to_string()));
+        }
+        if let Some(desk) = non_blank(&self.desk) {
+            sql.push_str(" AND desk_name = ?");
+            values.push(Value::Text(desk));
+        }
+        if let Some(service) = non_blank(&self.service) {
+            sql.push_str(" AND service = ?");
+            values.push(Value::Text(service));
+        }
+        if let Some(operator_id) = self.operator_id {
+            sql.push_str(" AND operator_id = ?");
+            values.push(Value::Integer(operator_id as i64));
+        }
+        if let Some(status) = self.status {
+            sql.push_str(" AND status = ?");
+            values.push(Value::Text(status.as_str().to_string()));
+        }
+        if let Some(search) = non_blank(&self.search) {
+            sql.push_str(
+                " AND (CAST(ticket_number AS TEXT) LIKE ? ESCAPE '\\'
+                       OR desk_name LIKE ? ESCAPE '\\'
+                       OR COALESCE(service, '') LIKE ? ESCAPE '\\')",
+            );
+            let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            let pattern = Value::Text(format!("%{}%", escaped));
+            values.extend([pattern.clone(), pattern.clone(), pattern]);
+        }
+        Ok((sql, values))
+    }
+}
+
+fn non_blank(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(String::from)
+}
+
+impl Database {
+    // Newest first.
+    pub fn query_history(&self, query: &HistoryQuery) -> QmsResult<HistoryPage> {
+        let (filter, values) = query.filter()?;
+        let limit = query.limit();
+        let conn = self.reader()?;
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) {}", filter),
+            rusqlite::params_from_iter(&values),
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, ticket_number, desk_name, service, operator_id,
+                    (SELECT name FROM staff WHERE staff.id = operator_id), status, created_at {}
+             ORDER BY id DESC LIMIT {} OFFSET {}",
+            filter, limit, query.offset
+        ))?;
+        let iter = stmt.query_map(rusqlite::params_from_iter(&values), |row| {
+            Ok(HistoryItem {
+                id: row.get(0)?,
+                ticket_number: row.get(1)?,
+                desk_name: row.get(2)?,
+                service: row.get(3)?,
+                operator_id: row.get(4)?,
+                operator_name: row.get(5)?,
+                status: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?;
+        let items = iter.collect::<Result<Vec<_>, _>>()?;
+
+        Ok(HistoryPage {
+            items,
+            total,
+            offset: query.offset,
+            limit,
+        })
+    }
+
+    // Every matching ticket with its lifecycle, oldest first; paging is ignored.
+    // Returns the number written.
+    pub fn export_history(&self, query: &HistoryQuery, path: &Path, format: ExportFormat) -> QmsResult<u64> {
+        let (filter, values) = query.filter()?;
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, ticket_number, desk_name, service, operator_id,
+                    (SELECT name FROM staff WHERE staff.id = operator_id), status,
+                    datetime(issued_at, 'localtime'), datetime(created_at, 'localtime'),
+                    datetime(ended_at, 'localtime'),
+                    ROUND(MAX(julianday(created_at) - julianday(issued_at), 0) * 1440, 2),
+                    ROUND(MAX(julianday(ended_at) - julianday(created_at), 0) * 1440, 2) {}
+             ORDER BY id",
+            filter
+        ))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(&values))?;
+
+        let mut writer = ExportWriter::create(path, format, "History", TICKET_COLUMNS)?;
+        while let Some(row) = rows.next()? {
+            writer.write(&TicketRecord {
+                id: row.get(0)?,
+                ticket_number: row.get(1)?,
+                desk_name: row.get(2)?,
+                service: row.get(3)?,
+                operator_id: row.get(4)?,
+                operator_name: row.get(5)?,
+                status: row.get(6)?,
+                issued_at: row.get(7)?,
+                called_at: row.get(8)?,
+                ended_at: row.get(9)?,
+                wait_minutes: row.get(10)?,
+                service_minutes: row.get(11)?,
+            })?;
+        }
+        writer.finish()
+    }
+}
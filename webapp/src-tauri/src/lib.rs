@@ -1,59 +1,175 @@
 use axum::{
-    extract::{Query, State},
+    extract::{ConnectInfo, Path, Query, State},
     response::sse::{Event, KeepAlive, Sse},
     routing::{get, post},
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Json},
     Router
 };
 use futures::stream::Stream;
 use local_ip_address::local_ip;
 use uuid::Uuid;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
 use serde::{Deserialize, Serialize};
 use std::{
-    sync::{Arc, Mutex},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
     time::Duration,
 };
 use tauri::{Emitter, Manager};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
 use tts::Tts;
 
+mod announcer;
+mod audio;
+mod audit;
+mod auth;
+mod clips;
+mod emergency;
+mod error;
+mod events;
+mod export;
+mod heatmap;
+mod history;
+mod migrations;
+mod playlist;
+mod pool;
+mod report;
+mod schedule;
+mod settings;
+mod spelling;
+mod staff;
+mod stats;
+mod targeting;
+mod voicepack;
+mod waiting;
+
+use announcer::{Announcement, Announcer, AnnouncerStatus, TtsSettings, VoiceInfo};
+use audio::{SoundEvent, SoundSettings};
+use audit::{Actor, Audit, AuditPage, AuditQuery};
+use auth::{AuthState, AuthStatus, LoginThrottle, Role, Session};
+use clips::ClipStore;
+use emergency::{Emergency, EmergencyState};
+use error::{QmsError, QmsResult};
+use events::{ServerEvent, Snapshot};
+use export::ExportFormat;
+use heatmap::{Heatmap, HeatmapComparison};
+use history::{HistoryItem, HistoryPage, HistoryQuery, TicketStatus};
+use playlist::{MediaLibrary, Slide, SlideKind};
+use pool::{PooledConn, ReaderPool};
+use report::{ReportFiles, ReportPeriod, Reports};
+use schedule::{AnnonceSchedule, AnnonceScheduler, AnnonceSpeech, TimeWindow};
+use staff::{DeskSession, Staff};
+use stats::{StatsQuery, StatsRange};
+use targeting::AnnonceTargets;
+use voicepack::{VoicePackInfo, VoicePacks};
+use waiting::{IssuedTicket, SlaSettings, WaitingMetrics};
+
 struct AppState {
     db: Arc<Database>,
-    app_handle: tauri::AppHandle,
-    tx: broadcast::Sender<String>,
-    tts: Arc<Mutex<Option<Tts>>>,
+    // None when the server runs without the app window, as in tests.
+    app_handle: Option<tauri::AppHandle>,
+    tx: broadcast::Sender<ServerEvent>,
+    announcer: Announcer,
+    emergency: EmergencyState,
+    media: Option<MediaLibrary>,
+    clips: Option<Arc<ClipStore>>,
+    // Failed desk PIN logins, per device address.
+    logins: LoginThrottle,
+    // Calls for the screens, sent in call order; their clips follow separately.
+    calls: mpsc::UnboundedSender<(Announcement, serde_json::Value)>,
 }
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, Clone, Debug)]
 struct EtatFile {
     compteur: i32,
     guichet: String,
 }
 
+const DEVICE_COLUMNS: &str = "id, name, token, service, group_name";
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Device {
     id: i32,
     name: String,
     token: String,
+    service: Option<String>,
+    group: Option<String>,
     status: Option<String>,
     ip_address: Option<String>
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl Device {
+    // Expects `DEVICE_COLUMNS` in that order.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Device {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            token: row.get(2)?,
+            service: row.get(3)?,
+            group: row.get(4)?,
+            ip_address: None,
+            status: None,
+        })
+    }
+
+    // For the audit log: everything but the token.
+    fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "service": self.service,
+            "group": self.group,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Annonce {
     id: i32,
     message: String,
     active: bool,
+    #[serde(flatten)]
+    schedule: AnnonceSchedule,
+    #[serde(flatten)]
+    speech: AnnonceSpeech,
+    // Empty shows it on every screen.
+    #[serde(default)]
+    targets: AnnonceTargets,
+    // Active and within its schedule right now.
+    #[serde(default)]
+    visible: bool,
 }
 
-#[derive(serde::Serialize, Clone, Debug)]
-pub struct HistoryItem {
-    id: i32,
-    ticket_number: i32,
-    desk_name: String,
-    created_at: String,
+const ANNONCE_COLUMNS: &str = "id, message, active, start_date, end_date, time_start, time_end,
+                               days_of_week, display_seconds, priority,
+                               audible, repeat_minutes, speak_times, targets";
+
+impl Annonce {
+    // Expects `ANNONCE_COLUMNS` in that order; `now` decides `visible`.
+    fn from_row(row: &rusqlite::Row, now: chrono::NaiveDateTime) -> rusqlite::Result<Self> {
+        let active: bool = row.get(2)?;
+        let schedule = AnnonceSchedule {
+            window: TimeWindow::from_row(row, 3)?,
+            display_seconds: row.get(8)?,
+            priority: row.get(9)?,
+        };
+        let speech = AnnonceSpeech {
+            audible: row.get(10)?,
+            repeat_minutes: row.get(11)?,
+            speak_times: AnnonceSpeech::times_from_column(&row.get::<_, String>(12)?),
+        };
+        Ok(Annonce {
+            id: row.get(0)?,
+            message: row.get(1)?,
+            active,
+            visible: active && schedule.is_live(now),
+            schedule,
+            speech,
+            targets: AnnonceTargets::from_column(&row.get::<_, String>(13)?),
+        })
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -65,62 +181,402 @@ pub struct TicketStats {
     duration_minutes: Option<f64>, // Option because the last client has no duration
 }
 
-async fn next_handler(
-    headers: HeaderMap,
-    State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
+// Resolves the `Authorization: Bearer <token>` header to the calling device.
+async fn authenticate_device(headers: &HeaderMap, state: &AppState) -> QmsResult<Device> {
     // 1. Authentication: Extract Token
     let token = match headers.get("Authorization") {
         Some(value) => value.to_str().unwrap_or("").replace("Bearer ", ""),
-        None => return (StatusCode::UNAUTHORIZED, "Missing Token").into_response(),
+        None => return Err(QmsError::Unauthorized("Missing Token".to_string())),
     };
 
     // 2. Verify Device against DB
     // We use a match statement to handle both Success (Some) and Failure (None)
-    match state.db.get_device_info(&token) {
-        Some((_id, device_name)) => {
-            println!("🟢 Button pressed by: {}", device_name);
+    let lookup = token.clone();
+    match state.db.run(move |db| db.get_device_info(&lookup)).await? {
+        Some(device) => Ok(device),
+        None => {
+            // Handle Invalid Token
+            println!("🔴 Login attempt with invalid token: {}", token);
+            Err(QmsError::Unauthorized("Invalid Token".to_string()))
+        }
+    }
+}
 
-            // A. Logic (Increment DB)
-            let nouveau_numero = state.db.incrementer(&device_name).compteur;
+async fn next_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let device_name = device.name;
+    println!("🟢 Button pressed by: {}", device_name);
+
+    if state.emergency.is_active() {
+        println!("🚨 Call refused during emergency: {}", device_name);
+        return Err(QmsError::Locked("Emergency in progress, calls are paused".to_string()));
+    }
 
-            // B. Emit to Tauri Frontend (Main Window)
-            let event_payload = EtatFile {
-                guichet: device_name.clone(),
-                compteur: nouveau_numero,
-            };
-            let _ = state.app_handle.emit("nouveau-message", &event_payload);
+    // A. Logic (Increment DB)
+    let desk = device_name.clone();
+    let service = device.service.clone();
+    let nouveau_numero = state
+        .db
+        .run(move |db| db.incrementer(&desk, service, &actor.audit("ticket_called")))
+        .await?
+        .compteur;
+
+    // B. Emit to Tauri Frontend (Main Window)
+    let event_payload = EtatFile {
+        guichet: device_name.clone(),
+        compteur: nouveau_numero,
+    };
+    if let Some(app_handle) = &state.app_handle {
+        let _ = app_handle.emit("nouveau-message", &event_payload);
+    }
+
+    // C. TTS Speak (queued, the response does not wait for it)
+    let announcement = Announcement::Call {
+        ticket: nouveau_numero,
+        desk: device_name.clone(),
+        service: device.service,
+    };
+    state.announcer.announce(announcement.clone());
+
+    // D. Prepare JSON Data
+    // Create a JSON Value, not a String, so we can reuse it easily
+    let response_json = serde_json::json!({
+        "guichet": device_name,
+        "compteur": nouveau_numero
+    });
+
+    // E. Broadcast update to SSE Screens (once its audio clip is ready)
+    let _ = state.calls.send((announcement, response_json.clone()));
+
+    // F. Response to Button (ESP32)
+    // Return the JSON object directly.
+    Ok((StatusCode::OK, Json(response_json)))
+}
+
+// Calls the desk's current ticket again (screens flash it, the announcer repeats it).
+async fn recall_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let device_name = device.name;
+
+    if state.emergency.is_active() {
+        return Err(QmsError::Locked("Emergency in progress, calls are paused".to_string()));
+    }
+
+    let desk = device_name.clone();
+    let recalled = state.db.run(move |db| db.recall_ticket(&desk, &actor.audit("ticket_recalled")));
+    let ticket = match recalled.await? {
+        Some(ticket) => ticket,
+        None => {
+            return Err(QmsError::NotFound(format!(
+                "No ticket to recall for {}",
+                device_name
+            )))
+        }
+    };
+    println!("🔁 Recall by: {} (ticket {})", device_name, ticket);
+
+    let announcement = Announcement::Recall {
+        ticket,
+        desk: device_name.clone(),
+        service: device.service,
+    };
+    state.announcer.announce(announcement.clone());
+
+    let response_json = serde_json::json!({
+        "guichet": device_name,
+        "compteur": ticket,
+        "recall": true
+    });
+    let _ = state.calls.send((announcement, response_json.clone()));
+
+    Ok((StatusCode::OK, Json(response_json)))
+}
+
+// The desk is done with its current ticket; the time until its next call is idle time.
+async fn done_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let desk = device.name.clone();
+    let ticket = state
+        .db
+        .run(move |db| db.end_ticket(&desk, TicketStatus::Served, &actor.audit("ticket_served")))
+        .await?
+        .ok_or_else(|| QmsError::NotFound(format!("No ticket in progress for {}", device.name)))?;
+    println!("☑️ Ticket {} done at {}", ticket, device.name);
+
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({ "guichet": device.name, "compteur": ticket, "done": true })),
+    ))
+}
+
+// The desk's current ticket was called but nobody came.
+async fn no_show_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let desk = device.name.clone();
+    let ticket = state
+        .db
+        .run(move |db| db.end_ticket(&desk, TicketStatus::NoShow, &actor.audit("ticket_no_show")))
+        .await?
+        .ok_or_else(|| QmsError::NotFound(format!("No ticket in progress for {}", device.name)))?;
+    println!("👻 Ticket {} no-show at {}", ticket, device.name);
+
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({ "guichet": device.name, "compteur": ticket, "no_show": true })),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+struct IssueParams {
+    service: Option<String>,
+}
 
-            // C. TTS Speak
-            let text_to_speak = format!("Client {}, to {}", nouveau_numero, device_name);
-            if let Ok(mut tts_guard) = state.tts.lock() {
-                if let Some(tts) = tts_guard.as_mut() {
-                    let _ = tts.speak(text_to_speak, true);
+// Kiosk / ticket printer. Without `?service=`, the kiosk's own service is used.
+async fn issue_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<IssueParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let service = params.service.or(device.service);
+    let ticket = state
+        .db
+        .run(move |db| db.issue_ticket(service, &actor.audit("ticket_issued")))
+        .await?;
+    Ok((StatusCode::OK, Json(ticket)))
+}
+
+// The desk goes on break until its next call.
+async fn close_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let desk = device.name.clone();
+    let result = state
+        .db
+        .run(move |db| db.close_desk(desk, &actor.audit("desk_closed")))
+        .await?;
+    Ok((StatusCode::OK, Json(serde_json::json!({ "guichet": device.name, "result": result }))))
+}
+
+#[derive(serde::Deserialize)]
+struct PinLogin {
+    staff_id: i32,
+    pin: String,
+}
+
+// An operator logs in at this desk with their PIN; their calls are attributed to them.
+async fn login_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(login): Json<PinLogin>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let throttle_key = addr.ip().to_string();
+    state.logins.check(&throttle_key)?;
+    let actor = Actor::device(&device, addr.ip());
+    let desk = device.name.clone();
+    let staff_id = login.staff_id;
+    let logins = state.logins.clone();
+    let session = state
+        .db
+        .run(move |db| {
+            let session = db.login_with_pin(&desk, staff_id, &login.pin, &actor.audit("operator_logged_in"));
+            match &session {
+                Ok(_) => logins.succeeded(&throttle_key),
+                Err(QmsError::Unauthorized(_)) => {
+                    logins.failed(&throttle_key);
+                    db.audit(
+                        &actor,
+                        "login_failed",
+                        None,
+                        Some(serde_json::json!({ "guichet": desk, "staff_id": staff_id })),
+                    )?;
                 }
+                Err(_) => {}
             }
+            session
+        })
+        .await?;
+    println!("👤 Operator {} logged in at {}", staff_id, device.name);
+    Ok((StatusCode::OK, Json(session)))
+}
+
+async fn logout_handler(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let device = authenticate_device(&headers, &state).await?;
+    let actor = Actor::device(&device, addr.ip());
+    let desk = device.name.clone();
+    let session = state
+        .db
+        .run(move |db| db.logout_operator(&desk, &actor.audit("operator_logged_out")))
+        .await?
+        .ok_or_else(|| QmsError::NotFound(format!("Nobody is logged in at {}", device.name)))?;
+    Ok((StatusCode::OK, Json(session)))
+}
 
-            // D. Prepare JSON Data
-            // Create a JSON Value, not a String, so we can reuse it easily
-            let response_json = serde_json::json!({
-                "guichet": device_name,
-                "compteur": nouveau_numero
+// Broadcasts each call to the screens as soon as it is made, then renders its clip in
+// the background and sends a `call_audio` follow-up with an `audio_url` they can play.
+// Clips render in parallel so a burst of calls does not queue behind the engine; one
+// that takes too long is dropped rather than played long after its call.
+fn spawn_call_broadcaster(
+    db: Arc<Database>,
+    clips: Option<Arc<ClipStore>>,
+    tx: broadcast::Sender<ServerEvent>,
+) -> mpsc::UnboundedSender<(Announcement, serde_json::Value)> {
+    let (calls_tx, mut calls_rx) = mpsc::unbounded_channel::<(Announcement, serde_json::Value)>();
+
+    tauri::async_runtime::spawn(async move {
+        while let Some((announcement, payload)) = calls_rx.recv().await {
+            let _ = tx.send(ServerEvent::Call(payload.clone()));
+            let Some(clips) = clips.clone() else {
+                continue;
+            };
+            let (db, tx) = (db.clone(), tx.clone());
+            tauri::async_runtime::spawn(async move {
+                match tokio::time::timeout(CLIP_TIMEOUT, render_clip(&db, clips, announcement)).await {
+                    Ok(Ok(Some(name))) => {
+                        let mut payload = payload;
+                        payload["audio_url"] = serde_json::json!(format!("/audio/{}", name));
+                        let _ = tx.send(ServerEvent::CallAudio(payload));
+                    }
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => eprintln!("❌ Clip rendering failed: {}", e),
+                    Err(_) => eprintln!("❌ Clip rendering took longer than {:?}, skipped", CLIP_TIMEOUT),
+                }
             });
+        }
+    });
 
-            // E. Broadcast update to SSE Screens
-            // (Convert to string only for the channel transmission)
-            let _ = state.tx.send(response_json.to_string());
+    calls_tx
+}
 
-            // F. Response to Button (ESP32)
-            // Return the JSON object directly.
-            (StatusCode::OK, Json(response_json)).into_response()
-        }
-        
-        None => {
-            // G. Handle Invalid Token
-            println!("🔴 Login attempt with invalid token: {}", token);
-            (StatusCode::UNAUTHORIZED, "Invalid Token").into_response()
-        }
+// Past this, the call is likely over and its clip would only confuse.
+const CLIP_TIMEOUT: Duration = Duration::from_secs(15);
+
+async fn render_clip(
+    db: &Arc<Database>,
+    clips: Arc<ClipStore>,
+    announcement: Announcement,
+) -> QmsResult<Option<String>> {
+    let (tts, sounds): (TtsSettings, SoundSettings) = db
+        .run(|db| Ok((db.get_setting(settings::TTS)?, db.get_setting(settings::SOUNDS)?)))
+        .await?;
+    tokio::task::spawn_blocking(move || clips.render(&announcement, &tts, &sounds)).await?
+}
+
+// --- HANDLER 3: AUDIO CLIPS (GET /audio/:file) ---
+// Not behind a token: browsers' <audio> cannot send one. Names are derived from the
+// text, but a clip only says what the screens already show.
+async fn audio_handler(
+    Path(file): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let path = state
+        .clips
+        .as_ref()
+        .and_then(|clips| clips.path_of(&file))
+        .ok_or_else(|| QmsError::NotFound(format!("No clip {}", file)))?;
+    serve_file(path, "audio/wav").await
+}
+
+async fn serve_file(path: PathBuf, content_type: &str) -> QmsResult<impl IntoResponse> {
+    let body = tokio::fs::read(&path)
+        .await
+        .map_err(|e| QmsError::NotFound(format!("Cannot read {}: {}", path.display(), e)))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CACHE_CONTROL, "max-age=3600".to_string()),
+        ],
+        body,
+    ))
+}
+
+// --- HANDLER 4: PLAYLIST (GET /playlist, GET /media/:file) ---
+// For screens that poll instead of listening to the `playlist` event.
+async fn playlist_handler(
+    Query(params): Query<SseParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let token = params.token;
+    if state.db.run(move |db| db.get_device_info(&token)).await?.is_none() {
+        return Err(QmsError::Unauthorized("Invalid Token".to_string()));
     }
+
+    let slides: Vec<Slide> = state
+        .db
+        .run(|db| db.get_playlist())
+        .await?
+        .into_iter()
+        .filter(|s| s.is_visible())
+        .collect();
+    Ok(Json(slides))
+}
+
+// Open like `/audio`: <img> and <video> cannot send a token.
+async fn media_handler(
+    Path(file): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    let path = state
+        .media
+        .as_ref()
+        .and_then(|media| media.path_of(&file))
+        .ok_or_else(|| QmsError::NotFound(format!("No media {}", file)))?;
+    let content_type = mime_guess::from_path(&path).first_or_octet_stream().to_string();
+    serve_file(path, &content_type).await
+}
+
+// --- HANDLER 5: HISTORY (GET /history?from=&to=&desk=&service=&status=&search=&offset=&limit=) ---
+async fn history_handler(
+    headers: HeaderMap,
+    Query(query): Query<HistoryQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    authenticate_device(&headers, &state).await?;
+    let page = state.db.run(move |db| db.query_history(&query)).await?;
+    Ok(Json(page))
+}
+
+// --- HANDLER 6: STATISTICS (GET /stats?from=&to=&desk=&service=) ---
+async fn stats_handler(
+    headers: HeaderMap,
+    Query(query): Query<StatsQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, QmsError> {
+    authenticate_device(&headers, &state).await?;
+    let stats = state.db.run(move |db| db.get_stats_range(&query)).await?;
+    Ok(Json(stats))
 }
 
 // --- HANDLER 2: SCREENS (SSE GET /events) ---
@@ -132,33 +588,52 @@ struct SseParams {
 async fn sse_handler(
     Query(params): Query<SseParams>,    // Extract ?token=...
     State(state): State<Arc<AppState>>,
-) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, (StatusCode, String)> {
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, QmsError> {
     
     // 3. Verify Token
-    let is_valid = {
-        let conn = state.db.conn.lock().unwrap();
-        // Check if token exists in 'devices' table
-        let mut stmt = conn.prepare("SELECT count(*) FROM devices WHERE token = ?1").unwrap();
-        let count: i32 = stmt.query_row(params![params.token], |row| row.get(0)).unwrap_or(0);
-        count > 0
+    // Check if token exists in 'devices' table
+    let token = params.token;
+    let device = match state.db.run(move |db| db.get_device_info(&token)).await? {
+        Some(device) => device,
+        None => {
+            println!("🔴 SSE Connection rejected: Invalid Token");
+            // Return 401 Unauthorized
+            return Err(QmsError::Unauthorized("Invalid Token".to_string()));
+        }
     };
 
-    if !is_valid {
-        println!("🔴 SSE Connection rejected: Invalid Token");
-        // Return 401 Unauthorized
-        return Err((StatusCode::UNAUTHORIZED, "Invalid Token".to_string()));
-    }
-
-    println!("✅ New Authorized Screen Connected (Token verified)");
+    println!("✅ New Authorized Screen Connected: {}", device.name);
 
-    // 4. Set up the Stream (Same as before)
+    // 4. Set up the Stream
+    // Subscribe before reading the snapshot so nothing falls in between.
     let mut rx = state.tx.subscribe();
+    let emergency = state.emergency.current();
+    let screen = device.clone();
+    let snapshot = state
+        .db
+        .run(move |db| {
+            Ok(Snapshot {
+                etat: db.get_current()?,
+                annonces: db
+                    .get_annonces()?
+                    .into_iter()
+                    .filter(|a| a.visible && a.targets.matches(&screen))
+                    .collect(),
+                playlist: db.get_playlist()?.into_iter().filter(|s| s.is_visible()).collect(),
+                emergency,
+            })
+        })
+        .await?;
 
     let stream = async_stream::stream! {
         yield Ok(Event::default().data("connected"));
+        yield events::snapshot(&snapshot);
 
-        while let Ok(msg) = rx.recv().await {
-            yield Ok(Event::default().data(msg));
+        while let Ok(event) = rx.recv().await {
+            // Annonces meant for other screens are left out.
+            if let Some(event) = targeting::for_device(event, &device) {
+                yield event.to_sse();
+            }
         }
     };
 
@@ -166,91 +641,158 @@ async fn sse_handler(
     Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
 }
 
+const DB_PATH: &str = "qms.db";
+
+// One writer serialised behind a mutex, plus a pool of read-only connections.
+// All methods are blocking: async callers go through `Database::run`.
 struct Database {
-    conn: Mutex<Connection>,
+    writer: Mutex<Connection>,
+    readers: ReaderPool,
 }
 
 impl Database {
-    fn init() -> Self {
-        let conn = Connection::open("qms.db").expect("Impossible d'ouvrir la DB");
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS etat_courant (
-                id INTEGER PRIMARY KEY,
-                valeur_compteur INTEGER NOT NULL,
-                dernier_guichet TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS devices (
-                id INTEGER PRIMARY KEY,
-                name TEXT UNIQUE NOT NULL,
-                token TEXT UNIQUE NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS annonces (
-                id INTEGER PRIMARY KEY,
-                message TEXT NOT NULL,
-                active BOOLEAN DEFAULT 1
-            )",
-            [],
-        )
-        .unwrap();
+    fn init(path: &std::path::Path) -> QmsResult<Self> {
+        let mut conn = Connection::open(path)?;
 
-        conn.execute(
-            "INSERT OR IGNORE INTO etat_courant (id, valeur_compteur, dernier_guichet) VALUES (1, 0, 'None')",
-            [],
-        ).unwrap();
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS historique (
-                id INTEGER PRIMARY KEY,
-                ticket_number INTEGER NOT NULL,
-                desk_name TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )
-        .unwrap();
+        // WAL lets readers keep working while a write transaction is open.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.busy_timeout(pool::BUSY_TIMEOUT)?;
 
-        Database {
-            conn: Mutex::new(conn),
-        }
+        // Apply any pending schema migrations before serving requests.
+        let version = migrations::run(&mut conn)?;
+        println!("🗄️ Database schema at version {}", version);
+
+        Ok(Database {
+            writer: Mutex::new(conn),
+            readers: ReaderPool::new(path),
+        })
     }
 
-    fn incrementer(&self, nom_guichet: &str) -> EtatFile {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "UPDATE etat_courant SET valeur_compteur = valeur_compteur + 1, dernier_guichet = ?1 WHERE id = 1",
-            params![nom_guichet],
-        ).unwrap();
+    // Runs blocking DB work on tokio's blocking pool so async handlers never stall
+    // the runtime's worker threads.
+    async fn run<T, F>(self: &Arc<Self>, f: F) -> QmsResult<T>
+    where
+        F: FnOnce(&Database) -> QmsResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = Arc::clone(self);
+        tokio::task::spawn_blocking(move || f(&db)).await?
+    }
 
-        let etat = self.lire_etat(&conn);
+    // A panicked holder cannot leave the connection half-used, so recover it instead of
+    // propagating the poison to every later request.
+    fn writer(&self) -> MutexGuard<'_, Connection> {
+        self.writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn reader(&self) -> QmsResult<PooledConn<'_>> {
+        self.readers.get()
+    }
+
+    // Assigns the next ticket to a desk. The counter update and the history row are
+    // one IMMEDIATE transaction, with its audit entry: concurrent calls are serialised
+    // by SQLite and a crash can never leave `etat_courant` and `historique` out of sync.
+    fn incrementer(&self, nom_guichet: &str, service: Option<String>, audit: &Audit) -> QmsResult<EtatFile> {
+        let mut conn = self.writer();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        // RETURNING gives back the exact value written by this UPDATE.
+        let etat = tx.query_row(
+            "UPDATE etat_courant SET valeur_compteur = valeur_compteur + 1, dernier_guichet = ?1
+             WHERE id = 1
+             RETURNING valeur_compteur, dernier_guichet",
+            params![nom_guichet],
+            |row| {
+                Ok(EtatFile {
+                    compteur: row.get(0)?,
+                    guichet: row.get(1)?,
+                })
+            },
+        )?;
 
         // 3. NEW: Save to History
-        conn.execute(
-            "INSERT INTO historique (ticket_number, desk_name) VALUES (?1, ?2)",
-            params![etat.compteur, nom_guichet],
-        )
-        .unwrap();
+        tx.execute(
+            "INSERT INTO historique (ticket_number, desk_name, service, issued_at, operator_id)
+             VALUES (?1, ?2, ?3, (
+                 SELECT issued_at FROM issued_tickets
+                 WHERE ticket_number = ?1
+                 AND reset_id = (SELECT COALESCE(MAX(id), 0) FROM historique WHERE ticket_number = -2)
+             ), (
+                 SELECT staff_id FROM desk_sessions WHERE desk_name = ?2 AND ended_at IS NULL
+             ))",
+            params![etat.compteur, nom_guichet, service],
+        )?;
+        audit.record(&tx, None, audit::value(&etat))?;
+
+        tx.commit()?;
+        Ok(etat)
+    }
+
+    // Marks the latest real ticket called by this desk since the last reset as
+    // recalled, and returns its number. A ticket already served or no-show keeps its
+    // outcome: there is nothing to recall.
+    fn recall_ticket(&self, desk_name: &str, audit: &Audit) -> QmsResult<Option<i32>> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let ticket = tx
+            .query_row(
+                "UPDATE historique SET status = 'recalled'
+                 WHERE id = (
+                     SELECT id FROM historique
+                     WHERE desk_name = ?1
+                     AND ticket_number >= 0
+                     AND id > (SELECT COALESCE(MAX(id), 0) FROM historique WHERE ticket_number = -2)
+                     ORDER BY id DESC LIMIT 1
+                 )
+                 AND ended_at IS NULL
+                 RETURNING ticket_number",
+                params![desk_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(ticket) = ticket {
+            audit.record(&tx, None, Some(serde_json::json!({ "guichet": desk_name, "compteur": ticket })))?;
+        }
+        tx.commit()?;
+        Ok(ticket)
+    }
 
-        etat
+    // Ends the desk's current ticket as served or no-show, if its last row since the
+    // reset is a ticket not already ended. Returns its number.
+    fn end_ticket(&self, desk_name: &str, status: TicketStatus, audit: &Audit) -> QmsResult<Option<i32>> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let ticket = tx
+            .query_row(
+                "UPDATE historique SET ended_at = CURRENT_TIMESTAMP, status = ?2
+                 WHERE id = (
+                     SELECT id FROM historique
+                     WHERE desk_name = ?1
+                     AND id > (SELECT COALESCE(MAX(id), 0) FROM historique WHERE ticket_number = -2)
+                     ORDER BY id DESC LIMIT 1
+                 )
+                 AND ticket_number >= 0
+                 AND ended_at IS NULL
+                 RETURNING ticket_number",
+                params![desk_name, status.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(ticket) = ticket {
+            audit.record(&tx, None, Some(serde_json::json!({ "guichet": desk_name, "compteur": ticket })))?;
+        }
+        tx.commit()?;
+        Ok(ticket)
     }
 
-    fn get_current(&self) -> EtatFile {
-        let conn = self.conn.lock().unwrap();
+    fn get_current(&self) -> QmsResult<EtatFile> {
+        let conn = self.reader()?;
         self.lire_etat(&conn)
     }
 
-    fn lire_etat(&self, conn: &Connection) -> EtatFile {
-        conn.query_row(
+    fn lire_etat(&self, conn: &Connection) -> QmsResult<EtatFile> {
+        let etat = conn.query_row(
             "SELECT valeur_compteur, dernier_guichet FROM etat_courant WHERE id = 1",
             [],
             |row| {
@@ -259,160 +801,284 @@ impl Database {
                     guichet: row.get(1)?,
                 })
             },
-        )
-        .unwrap()
+        )?;
+        Ok(etat)
     }
 
-    fn register_device(&self, name: String) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
+    // None when a device of that name already exists.
+    fn register_device(&self, name: String, audit: &Audit) -> QmsResult<Option<Device>> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(QmsError::Validation("Device name cannot be empty".to_string()));
+        }
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
 
         let token = Uuid::new_v4().to_string();
 
-        conn.execute(
-            "INSERT OR IGNORE INTO devices (name, token) VALUES (?1, ?2)",
-            params![name, token],
+        let device = tx
+            .query_row(
+                &format!(
+                    "INSERT OR IGNORE INTO devices (name, token) VALUES (?1, ?2) RETURNING {}",
+                    DEVICE_COLUMNS
+                ),
+                params![name, token],
+                Device::from_row,
+            )
+            .optional()?;
+        if let Some(device) = &device {
+            audit.record(&tx, None, Some(device.summary()))?;
+        }
+        tx.commit()?;
+        Ok(device)
+    }
+
+    fn device_by_id(&self, conn: &Connection, id: i32) -> QmsResult<Device> {
+        conn.query_row(
+            &format!("SELECT {} FROM devices WHERE id = ?1", DEVICE_COLUMNS),
+            params![id],
+            Device::from_row,
         )
-        .map_err(|e| e.to_string())?;
-        Ok(())
+        .optional()?
+        .ok_or_else(|| QmsError::NotFound(format!("Device {} not found", id)))
     }
 
-    fn get_device_info(&self, token: &str) -> Option<(i32, String)> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn
-            .prepare("SELECT id, name FROM devices WHERE token = ?1")
-            .unwrap();
+    fn get_device_info(&self, token: &str) -> QmsResult<Option<Device>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM devices WHERE token = ?1", DEVICE_COLUMNS))?;
 
-        let result = stmt.query_row(params![token], |row| Ok((row.get(0)?, row.get(1)?)));
+        let result = stmt.query_row(params![token], Device::from_row).optional()?;
 
-        result.ok()
+        Ok(result)
     }
 
-    fn delete_device(&self, id: i32) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM devices WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
+    fn set_device_service(&self, id: i32, service: Option<String>, audit: &Audit) -> QmsResult<()> {
+        // Blank means "no service".
+        let service = service.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = self.device_by_id(&tx, id)?;
+        tx.execute(
+            "UPDATE devices SET service = ?1 WHERE id = ?2",
+            params![service, id],
+        )?;
+        let after = self.device_by_id(&tx, id)?;
+        audit.record(&tx, Some(before.summary()), Some(after.summary()))?;
+        tx.commit()?;
         Ok(())
     }
 
-    fn get_all_devices(&self) -> Vec<Device> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM devices").unwrap();
+    fn set_device_group(&self, id: i32, group: Option<String>, audit: &Audit) -> QmsResult<()> {
+        // Blank means "no group".
+        let group = group.map(|g| g.trim().to_string()).filter(|g| !g.is_empty());
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = self.device_by_id(&tx, id)?;
+        tx.execute(
+            "UPDATE devices SET group_name = ?1 WHERE id = ?2",
+            params![group, id],
+        )?;
+        let after = self.device_by_id(&tx, id)?;
+        audit.record(&tx, Some(before.summary()), Some(after.summary()))?;
+        tx.commit()?;
+        Ok(())
+    }
 
-        let devices_iter = stmt
-            .query_map([], |row| {
-                Ok(Device {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    token: row.get(2)?,
-                    ip_address: None,
-                    status: None
-                })
-            })
-            .unwrap();
+    fn delete_device(&self, id: i32, audit: &Audit) -> QmsResult<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = self.device_by_id(&tx, id)?;
+        tx.execute("DELETE FROM devices WHERE id = ?1", params![id])?;
+        audit.record(&tx, Some(before.summary()), None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_all_devices(&self) -> QmsResult<Vec<Device>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM devices", DEVICE_COLUMNS))?;
 
-        devices_iter.map(|d| d.unwrap()).collect()
+        let devices_iter = stmt.query_map([], Device::from_row)?;
+
+        let devices = devices_iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(devices)
     }
 
     // --- GESTION DES ANNONCES (NOUVEAU) ---
 
-    fn add_annonce(&self, message: String) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO annonces (message) VALUES (?1)",
-            params![message],
-        )
-        .map_err(|e| e.to_string())?;
-        Ok(())
+    fn add_annonce(
+        &self,
+        message: String,
+        schedule: AnnonceSchedule,
+        speech: AnnonceSpeech,
+        targets: AnnonceTargets,
+        audit: &Audit,
+    ) -> QmsResult<Annonce> {
+        if message.trim().is_empty() {
+            return Err(QmsError::Validation("Announcement cannot be empty".to_string()));
+        }
+        schedule.validate()?;
+        speech.validate()?;
+        targets.validate()?;
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO annonces (message, start_date, end_date, time_start, time_end,
+                                   days_of_week, display_seconds, priority,
+                                   audible, repeat_minutes, speak_times, targets)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                message,
+                schedule.window.start_date,
+                schedule.window.end_date,
+                schedule.window.time_start,
+                schedule.window.time_end,
+                schedule.window.days_mask(),
+                schedule.display_seconds,
+                schedule.priority,
+                speech.audible,
+                speech.repeat_minutes,
+                speech.times_column(),
+                targets.to_column()
+            ],
+        )?;
+        let annonce = self.annonce_by_id(&tx, tx.last_insert_rowid() as i32)?;
+        audit.record(&tx, None, audit::value(&annonce))?;
+        tx.commit()?;
+        Ok(annonce)
     }
 
-    fn get_annonces(&self) -> Vec<Annonce> {
-        let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM annonces").unwrap();
-        let iter = stmt
-            .query_map([], |row| {
-                Ok(Annonce {
-                    id: row.get(0)?,
-                    message: row.get(1)?,
-                    active: row.get(2)?,
-                })
-            })
-            .unwrap();
-        iter.map(|a| a.unwrap()).collect()
+    // Highest priority first, then in creation order.
+    fn get_annonces(&self) -> QmsResult<Vec<Annonce>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM annonces ORDER BY priority DESC, id",
+            ANNONCE_COLUMNS
+        ))?;
+        let now = schedule::now();
+        let iter = stmt.query_map([], |row| Annonce::from_row(row, now))?;
+        let annonces = iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(annonces)
     }
 
-    fn update_annonce_message(&self, id: i32, new_message: String) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
+    // Read back through the connection that just wrote it.
+    fn annonce_by_id(&self, conn: &Connection, id: i32) -> QmsResult<Annonce> {
+        conn.query_row(
+            &format!("SELECT {} FROM annonces WHERE id = ?1", ANNONCE_COLUMNS),
+            params![id],
+            |row| Annonce::from_row(row, schedule::now()),
+        )
+        .optional()?
+        .ok_or_else(|| QmsError::NotFound(format!("Announcement {} not found", id)))
+    }
+
+    // The schedule, speech and target settings are left as they are when None.
+    fn update_annonce_message(
+        &self,
+        id: i32,
+        new_message: String,
+        schedule: Option<AnnonceSchedule>,
+        speech: Option<AnnonceSpeech>,
+        targets: Option<AnnonceTargets>,
+        audit: &Audit,
+    ) -> QmsResult<Annonce> {
+        if new_message.trim().is_empty() {
+            return Err(QmsError::Validation("Announcement cannot be empty".to_string()));
+        }
+        if let Some(schedule) = &schedule {
+            schedule.validate()?;
+        }
+        if let Some(speech) = &speech {
+            speech.validate()?;
+        }
+        if let Some(targets) = &targets {
+            targets.validate()?;
+        }
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
 
-        conn.execute(
+        let before = self.annonce_by_id(&tx, id)?;
+        tx.execute(
             "UPDATE annonces SET message = ?1 WHERE id = ?2",
             params![new_message, id],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
+        if let Some(schedule) = schedule {
+            tx.execute(
+                "UPDATE annonces
+                 SET start_date = ?1, end_date = ?2, time_start = ?3, time_end = ?4,
+                     days_of_week = ?5, display_seconds = ?6, priority = ?7
+                 WHERE id = ?8",
+                params![
+                    schedule.window.start_date,
+                    schedule.window.end_date,
+                    schedule.window.time_start,
+                    schedule.window.time_end,
+                    schedule.window.days_mask(),
+                    schedule.display_seconds,
+                    schedule.priority,
+                    id
+                ],
+            )?;
+        }
+        if let Some(speech) = speech {
+            tx.execute(
+                "UPDATE annonces SET audible = ?1, repeat_minutes = ?2, speak_times = ?3 WHERE id = ?4",
+                params![speech.audible, speech.repeat_minutes, speech.times_column(), id],
+            )?;
+        }
+        if let Some(targets) = targets {
+            tx.execute(
+                "UPDATE annonces SET targets = ?1 WHERE id = ?2",
+                params![targets.to_column(), id],
+            )?;
+        }
 
-        Ok(())
+        let annonce = self.annonce_by_id(&tx, id)?;
+        audit.record(&tx, audit::value(&before), audit::value(&annonce))?;
+        tx.commit()?;
+        Ok(annonce)
     }
 
-    fn set_annonce_active(&self, id: i32, is_active: bool) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
+    fn set_annonce_active(&self, id: i32, is_active: bool, audit: &Audit) -> QmsResult<Annonce> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
 
-        conn.execute(
+        let before = self.annonce_by_id(&tx, id)?;
+        tx.execute(
             "UPDATE annonces SET active = ?1 WHERE id = ?2",
             params![is_active, id],
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
-        Ok(())
+        let annonce = self.annonce_by_id(&tx, id)?;
+        audit.record(&tx, audit::value(&before), audit::value(&annonce))?;
+        tx.commit()?;
+        Ok(annonce)
     }
 
-    fn delete_annonce(&self, id: i32) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM annonces WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
+    fn delete_annonce(&self, id: i32, audit: &Audit) -> QmsResult<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = self.annonce_by_id(&tx, id)?;
+        tx.execute("DELETE FROM annonces WHERE id = ?1", params![id])?;
+        audit.record(&tx, audit::value(&before), None)?;
+        tx.commit()?;
         Ok(())
     }
 
-    pub fn get_history(&self) -> Vec<HistoryItem> {
-        let conn = self.conn.lock().unwrap();
-
-        // Logic:
-        // 1. Find the ID of the last "Reset" (-2). If none, use 0.
-        // 2. Fetch valid tickets (>= 0) that came AFTER that ID.
-        // 3. Skip the first one (OFFSET 1) because it is the "Current" ticket on screen.
-        // 4. Take the next 5.
-        let sql = "
-            WITH LastReset AS (
-                SELECT COALESCE(MAX(id), 0) as reset_id 
-                FROM historique 
-                WHERE ticket_number = -2
-            )
-            SELECT id, ticket_number, desk_name, created_at 
-            FROM historique, LastReset
-            WHERE id > LastReset.reset_id 
-            AND ticket_number >= 0  -- Ignored -1 (Close) and -2 (Reset)
-            ORDER BY id DESC 
-            LIMIT 5 OFFSET 1;
-            ";
-
-        let mut stmt = conn.prepare(sql).unwrap();
-
-        let iter = stmt
-            .query_map([], |row| {
-                Ok(HistoryItem {
-                    id: row.get(0)?,
-                    ticket_number: row.get(1)?,
-                    desk_name: row.get(2)?,
-                    created_at: row.get(3)?,
-                })
-            })
-            .unwrap();
-
-        iter.map(|h| h.unwrap()).collect()
+    // Skips the ticket on screen and takes the 5 before it, since the last reset.
+    pub fn get_history(&self) -> QmsResult<Vec<HistoryItem>> {
+        Ok(self.query_history(&HistoryQuery::display())?.into_items())
     }
 
     // inside impl Database { ... }
 
-    pub fn get_desk_statistics(&self, desk_name: &str) -> Vec<TicketStats> {
-        let conn = self.conn.lock().unwrap();
+    pub fn get_desk_statistics(&self, desk_name: &str) -> QmsResult<Vec<TicketStats>> {
+        let conn = self.reader()?;
 
         let sql = "
             -- 1. Find the Global Reset Time (-2)
@@ -454,7 +1120,7 @@ impl Database {
             ORDER BY id DESC;
             ";
 
-        let mut stmt = conn.prepare(sql).unwrap();
+        let mut stmt = conn.prepare(sql)?;
 
         // We pass 'desk' twice: once for the Subquery, once for the Main Query
         let iter = stmt
@@ -466,16 +1132,17 @@ impl Database {
                     end_time: row.get(3).ok(),
                     duration_minutes: row.get(4).ok(),
                 })
-            })
-            .unwrap();
+            })?;
 
-        iter.filter_map(Result::ok).collect()
+        let stats = iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(stats)
     }
 
-    pub fn close_desk(&self, desk_name: String) -> Result<String, String> {
-        let conn = self.conn.lock().unwrap();
+    pub fn close_desk(&self, desk_name: String, audit: &Audit) -> QmsResult<String> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
 
-        let last_ticket_result: Result<i32, rusqlite::Error> = conn.query_row(
+        let last_ticket_result: Result<i32, rusqlite::Error> = tx.query_row(
             "SELECT ticket_number FROM historique WHERE desk_name = ?1 ORDER BY id DESC LIMIT 1",
             params![desk_name],
             |row| row.get(0),
@@ -496,19 +1163,24 @@ impl Database {
 
             // Case C: Success (We try to close it)
             Ok(_ticket_num) => {
-                let insert_result = conn.execute(
-                    "INSERT INTO historique (ticket_number, desk_name) VALUES (-1, ?1)",
-                    params![desk_name],
-                );
+                let insert_result = tx
+                    .execute(
+                        "INSERT INTO historique (ticket_number, desk_name, operator_id)
+                         VALUES (-1, ?1, (SELECT staff_id FROM desk_sessions WHERE desk_name = ?1 AND ended_at IS NULL))",
+                        params![desk_name],
+                    )
+                    .map_err(QmsError::from)
+                    .and_then(|_| audit.record(&tx, None, Some(serde_json::json!({ "guichet": desk_name }))));
 
                 match insert_result {
                     Ok(_) => {
+                        tx.commit()?;
                         println!("✅ Desk '{}' closed.", desk_name);
                         Ok("SUCCESS".to_string()) // Send this code to JS
                     }
                     Err(e) => {
                         eprintln!("❌ DB Error: {}", e);
-                        Err(e.to_string()) // Send actual error to JS (Promise reject)
+                        Err(e) // Send actual error to JS (Promise reject)
                     }
                 }
             }
@@ -516,39 +1188,69 @@ impl Database {
             // Case D: Database Error during check
             Err(e) => {
                 eprintln!("❌ DB Error: {}", e);
-                Err(e.to_string())
+                Err(e.into())
             }
         }
     }
 
-    fn reset_display_history(&self) -> EtatFile {
-        let conn = self.conn.lock().unwrap();
+    fn reset_display_history(&self, audit: &Audit) -> QmsResult<EtatFile> {
+        let mut conn = self.writer();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let before = self.lire_etat(&tx)?;
 
         // Insert the -2 marker.
         // We can use a generic name like "Admin" or "System" for the desk_name.
-        conn.execute(
+        tx.execute(
             "INSERT INTO historique (ticket_number, desk_name) VALUES (-2, 'System')",
             [],
-        )
-        .unwrap();
+        )?;
 
         println!("History display reset marker (-2) added.");
 
-        conn.execute(
+        tx.execute(
             "UPDATE etat_courant SET valeur_compteur = 0, dernier_guichet = 'Reset' WHERE id = 1",
             [],
-        )
-        .unwrap();
-        self.lire_etat(&conn)
+        )?;
+        let etat = self.lire_etat(&tx)?;
+        audit.record(&tx, audit::value(&before), audit::value(&etat))?;
+
+        tx.commit()?;
+        Ok(etat)
     }
 }
 
+// Route Definition
+fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/events", get(sse_handler)) // For SCREENS (SSE)
+        .route("/next", post(next_handler)) // For BUTTONS (POST)
+        .route("/recall", post(recall_handler)) // Repeat the desk's current ticket
+        .route("/audio/:file", get(audio_handler)) // Call clips for remote screens
+        .route("/playlist", get(playlist_handler)) // Idle-screen slides
+        .route("/media/:file", get(media_handler)) // Slide images and videos
+        .route("/history", get(history_handler)) // Ticket history, filtered and paged
+        .route("/done", post(done_handler)) // Current ticket finished
+        .route("/close", post(close_handler)) // Desk on break
+        .route("/noshow", post(no_show_handler)) // Current ticket did not come
+        .route("/issue", post(issue_handler)) // Kiosk hands out a ticket
+        .route("/login", post(login_handler)) // Operator PIN login at the desk
+        .route("/logout", post(logout_handler))
+        .route("/stats", get(stats_handler)) // Statistics over a date range
+        .with_state(state)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .setup(|app| {
             let app_handle = app.handle().clone();
-            let db = std::sync::Arc::new(Database::init());
+            let db = match Database::init(std::path::Path::new(DB_PATH)) {
+                Ok(db) => std::sync::Arc::new(db),
+                Err(e) => {
+                    eprintln!("❌ Database initialization failed: {}", e);
+                    return Err(e.into());
+                }
+            };
             app.manage(db.clone());
 
             // Initialize TTS once (Shared across threads)
@@ -559,10 +1261,28 @@ pub fn run() {
                     None
                 }
             };
-            let tts = Arc::new(Mutex::new(tts_instance));
+            // Recorded voices, used instead of TTS for the languages that pick one.
+            let data_dir = app.path().app_data_dir()?;
+            let voice_packs = VoicePacks::open(data_dir.join("voice_packs"));
+            app.manage(voice_packs.clone());
+
+            // The announcer thread owns the engine and speaks calls one after another.
+            let tts_settings: TtsSettings = db.get_setting(settings::TTS)?;
+            let sound_settings: SoundSettings = db.get_setting(settings::SOUNDS)?;
+            let announcer = Announcer::spawn(tts_instance, tts_settings, sound_settings, voice_packs.clone());
+            app.manage(announcer.clone());
 
             // Create Broadcast Channel (Capacity 100)
             let (tx, _rx) = broadcast::channel(100);
+            app.manage(tx.clone());
+
+            let emergency = EmergencyState::default();
+            app.manage(emergency.clone());
+
+            // Locked until someone logs in; the display page works regardless.
+            app.manage(AuthState::default());
+            let logins = LoginThrottle::default();
+            app.manage(logins.clone());
 
             let heartbeat_tx = tx.clone();
             tauri::async_runtime::spawn(async move {
@@ -570,35 +1290,81 @@ pub fn run() {
                     tokio::time::sleep(Duration::from_secs(5)).await;
                     // We send a "PING" message.
                     // The ESP32 will see: "data: PING"
-                    if let Err(e) = heartbeat_tx.send("PING".to_string()) {
+                    if let Err(e) = heartbeat_tx.send(ServerEvent::Ping) {
                         // If no clients are connected, this might error, which is fine
                         eprintln!("Heartbeat skipped (no listeners): {}", e);
                     }
                 }
             });
 
+            // Clips of each call for screens with their own speakers.
+            let clips = match ClipStore::open(data_dir.join("clips"), voice_packs) {
+                Ok(clips) => Some(Arc::new(clips)),
+                Err(e) => {
+                    eprintln!("❌ Audio clips disabled: {}", e);
+                    None
+                }
+            };
+            let calls = spawn_call_broadcaster(db.clone(), clips.clone(), tx.clone());
+
+            // Daily and weekly summaries, written after each counter reset.
+            match Reports::open(data_dir.join("reports")) {
+                Ok(reports) => {
+                    app.manage(reports);
+                }
+                Err(e) => eprintln!("❌ Reports disabled: {}", e),
+            }
+
+            // Slides for idle screens.
+            let media = match MediaLibrary::open(data_dir.join("media")) {
+                Ok(media) => {
+                    app.manage(media.clone());
+                    Some(media)
+                }
+                Err(e) => {
+                    eprintln!("❌ Playlist media disabled: {}", e);
+                    None
+                }
+            };
+
+            // Switches scheduled annonces on and off and pushes them to the screens.
+            // Audible ones are also queued on the announcer, behind any waiting call.
+            let scheduler = AnnonceScheduler::spawn(db.clone(), app_handle.clone(), tx.clone(), announcer.clone());
+            app.manage(scheduler);
+
             // Create State to pass to handlers
             let state = Arc::new(AppState {
                 db: db.clone(),
-                app_handle,
+                app_handle: Some(app_handle),
                 tx,
-                tts,
+                announcer,
+                emergency,
+                media,
+                clips,
+                logins,
+                calls,
             });
 
             // Spawn the Web Server
             tauri::async_runtime::spawn(async move {
-                // Route Definition
-                let app = Router::new()
-                    .route("/events", get(sse_handler)) // For SCREENS (SSE)
-                    .route("/next", post(next_handler)) // For BUTTONS (POST)
-                    .with_state(state);
+                let app = router(state);
 
                 let addr = "0.0.0.0:8765";
-                let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+                let listener = match tokio::net::TcpListener::bind(addr).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        eprintln!("❌ Cannot bind {}: {}", addr, e);
+                        return;
+                    }
+                };
 
                 println!("🚀 Server SSE/HTTP ready on http://{}", addr);
 
-                axum::serve(listener, app).await.unwrap();
+                // Peer addresses go into the audit log.
+                let app = app.into_make_service_with_connect_info::<SocketAddr>();
+                if let Err(e) = axum::serve(listener, app).await {
+                    eprintln!("❌ HTTP server stopped: {}", e);
+                }
             });
 
             Ok(())
@@ -613,10 +1379,56 @@ pub fn run() {
             register_device,
             get_annonces,
             get_history,
+            query_history,
             get_stats,
+            get_stats_range,
+            get_heatmap,
+            compare_heatmaps,
+            issue_ticket,
+            get_waiting_metrics,
+            get_sla_settings,
+            set_sla_settings,
+            close_desk,
             update_annonce_message,
             set_annonce_active,
-            delete_device
+            delete_device,
+            set_device_service,
+            set_device_group,
+            get_announcer_status,
+            get_tts_settings,
+            set_tts_settings,
+            list_voices,
+            list_voice_packs,
+            reload_voice_packs,
+            preview_tts,
+            get_sound_settings,
+            set_sound_settings,
+            preview_sound,
+            announce_message,
+            get_emergency,
+            start_emergency,
+            clear_emergency,
+            get_playlist,
+            upload_slide,
+            add_text_slide,
+            update_slide,
+            reorder_slides,
+            delete_slide,
+            get_staff,
+            add_staff,
+            update_staff,
+            login_operator,
+            logout_operator,
+            get_desk_sessions,
+            get_auth_status,
+            login,
+            logout,
+            create_first_admin,
+            query_audit_log,
+            export_audit_log,
+            export_history,
+            export_stats,
+            generate_report
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -639,67 +1451,1136 @@ fn get_machine_ip() -> String {
  */
 
 #[tauri::command]
-fn reset_counter(state: tauri::State<std::sync::Arc<Database>>) -> EtatFile {
-    state.reset_display_history()
+async fn reset_counter(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    app_handle: tauri::AppHandle,
+) -> QmsResult<EtatFile> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let (etat, closing) = state
+        .run(move |db| {
+            // Worked out before the reset, which starts a new session.
+            let closing = db.closing_reports().unwrap_or_else(|e| {
+                eprintln!("❌ Cannot work out the reports to generate: {}", e);
+                Vec::new()
+            });
+            let etat = db.reset_display_history(&actor.audit("counter_reset"))?;
+            Ok((etat, closing))
+        })
+        .await?;
+
+    // The reports of what the reset closed, in the background: the reset itself is done.
+    if let Some(reports) = app_handle.try_state::<Reports>() {
+        let reports = reports.inner().clone();
+        let db = state.inner().clone();
+        tauri::async_runtime::spawn(async move {
+            for (period, from, to) in closing {
+                let reports = reports.clone();
+                if let Err(e) = db.run(move |db| db.generate_report(&reports, period, from, to)).await {
+                    eprintln!("❌ Cannot generate the report for {} to {}: {}", from, to, e);
+                }
+            }
+        });
+    }
+    Ok(etat)
 }
 
 #[tauri::command]
-fn get_counter_state(state: tauri::State<std::sync::Arc<Database>>) -> EtatFile {
-    state.get_current()
+async fn get_counter_state(state: tauri::State<'_, Arc<Database>>) -> QmsResult<EtatFile> {
+    state.run(|db| db.get_current()).await
 }
 
 #[tauri::command]
-fn get_all_devices(state: tauri::State<Arc<Database>>) -> Vec<Device> {
-    state.get_all_devices()
+async fn get_all_devices(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Vec<Device>> {
+    auth.require(Role::Admin)?;
+    state.run(|db| db.get_all_devices()).await
 }
 
 #[tauri::command]
-fn delete_device(state: tauri::State<Arc<Database>>, id: i32) -> Result<(), String> {
-    state.delete_device(id)
+async fn delete_device(
+    state: tauri::State<'_, Arc<Database>>,
+    id: i32,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    state
+        .run(move |db| db.delete_device(id, &actor.audit("device_deleted")))
+        .await
 }
 
-/**
- * ANNOUNCEMENT *********************************************************
- */
-
 #[tauri::command]
-fn get_annonces(state: tauri::State<Arc<Database>>) -> Vec<Annonce> {
-    state.get_annonces()
+async fn set_device_service(
+    state: tauri::State<'_, Arc<Database>>,
+    id: i32,
+    service: Option<String>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    state
+        .run(move |db| db.set_device_service(id, service, &actor.audit("device_service_changed")))
+        .await
 }
 
+// Screens connected at the time pick up the new group when they reconnect.
 #[tauri::command]
-fn add_annonce(state: tauri::State<Arc<Database>>, message: String) -> Result<(), String> {
-    state.add_annonce(message)
+async fn set_device_group(
+    state: tauri::State<'_, Arc<Database>>,
+    id: i32,
+    group: Option<String>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    state
+        .run(move |db| db.set_device_group(id, group, &actor.audit("device_group_changed")))
+        .await
 }
 
+/**
+ * ANNOUNCEMENT *********************************************************
+ */
+
+// With a device `token`, only the annonces meant for that screen; without, all of them.
+#[tauri::command]
+async fn get_annonces(state: tauri::State<'_, Arc<Database>>, token: Option<String>) -> QmsResult<Vec<Annonce>> {
+    state
+        .run(move |db| {
+            let annonces = db.get_annonces()?;
+            let Some(token) = token else {
+                return Ok(annonces);
+            };
+            let device = db
+                .get_device_info(&token)?
+                .ok_or_else(|| QmsError::Unauthorized("Invalid Token".to_string()))?;
+            Ok(annonces.into_iter().filter(|a| a.targets.matches(&device)).collect())
+        })
+        .await
+}
+
+// Without a schedule the annonce shows whenever it is active, as before.
 #[tauri::command]
-fn update_annonce_message(state: tauri::State<Arc<Database>>, id: i32, message: String) -> Result<(), String> {
-    state.update_annonce_message(id, message)
+async fn add_annonce(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    message: String,
+    schedule: Option<AnnonceSchedule>,
+    speech: Option<AnnonceSpeech>,
+    targets: Option<AnnonceTargets>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Annonce> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let annonce = state
+        .run(move |db| {
+            db.add_annonce(
+                message,
+                schedule.unwrap_or_default(),
+                speech.unwrap_or_default(),
+                targets.unwrap_or_default(),
+                &actor.audit("annonce_added"),
+            )
+        })
+        .await?;
+    scheduler.publish(ServerEvent::AnnonceAdded(annonce.clone()));
+    Ok(annonce)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn update_annonce_message(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    id: i32,
+    message: String,
+    schedule: Option<AnnonceSchedule>,
+    speech: Option<AnnonceSpeech>,
+    targets: Option<AnnonceTargets>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Annonce> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let annonce = state
+        .run(move |db| {
+            db.update_annonce_message(id, message, schedule, speech, targets, &actor.audit("annonce_updated"))
+        })
+        .await?;
+    scheduler.publish(ServerEvent::AnnonceUpdated(annonce.clone()));
+    Ok(annonce)
 }
 
 #[tauri::command]
-fn set_annonce_active(state: tauri::State<Arc<Database>>, id: i32, is_active: bool) -> Result<(), String> {
-    state.set_annonce_active(id, is_active)
+async fn set_annonce_active(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    id: i32,
+    is_active: bool,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Annonce> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let annonce = state
+        .run(move |db| db.set_annonce_active(id, is_active, &actor.audit("annonce_toggled")))
+        .await?;
+    scheduler.publish(ServerEvent::AnnonceToggled(annonce.clone()));
+    Ok(annonce)
 }
 
 #[tauri::command]
-fn delete_annonce(state: tauri::State<Arc<Database>>, id: i32) -> Result<(), String> {
-    state.delete_annonce(id)
+async fn delete_annonce(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    id: i32,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    state
+        .run(move |db| db.delete_annonce(id, &actor.audit("annonce_deleted")))
+        .await?;
+    scheduler.publish(ServerEvent::AnnonceDeleted { id });
+    Ok(())
 }
 
 
 #[tauri::command]
-fn register_device(state: tauri::State<Arc<Database>>, name: String) -> Result<(), String> {
+async fn register_device(
+    state: tauri::State<'_, Arc<Database>>,
+    name: String,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
     println!("{}", name);
-    state.register_device(name)
+    state
+        .run(move |db| {
+            db.register_device(name, &actor.audit("device_registered"))?;
+            Ok(())
+        })
+        .await
+}
+
+#[tauri::command]
+async fn get_history(state: tauri::State<'_, Arc<Database>>) -> QmsResult<Vec<HistoryItem>> {
+    state.run(|db| db.get_history()).await
 }
 
 #[tauri::command]
-fn get_history(state: tauri::State<Arc<Database>>) -> Vec<HistoryItem> {
-    state.get_history()
+async fn query_history(
+    state: tauri::State<'_, Arc<Database>>,
+    query: Option<HistoryQuery>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<HistoryPage> {
+    auth.require(Role::Supervisor)?;
+    state.run(move |db| db.query_history(&query.unwrap_or_default())).await
 }
 
 #[tauri::command]
-fn get_stats(desk_name: String, state: tauri::State<std::sync::Arc<Database>>) -> Vec<TicketStats> {
-    state.get_desk_statistics(&desk_name)
+async fn get_stats(
+    desk_name: String,
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Vec<TicketStats>> {
+    auth.require(Role::Operator)?;
+    state.run(move |db| db.get_desk_statistics(&desk_name)).await
+}
+
+// Per desk, per service and for the whole site. Unlike `get_stats`, not limited to
+// the current session.
+#[tauri::command]
+async fn get_stats_range(
+    state: tauri::State<'_, Arc<Database>>,
+    query: Option<StatsQuery>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<StatsRange> {
+    auth.require(Role::Supervisor)?;
+    state.run(move |db| db.get_stats_range(&query.unwrap_or_default())).await
+}
+
+// Calls per weekday and hour over the period.
+#[tauri::command]
+async fn get_heatmap(
+    state: tauri::State<'_, Arc<Database>>,
+    query: Option<StatsQuery>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Heatmap> {
+    auth.require(Role::Supervisor)?;
+    state.run(move |db| db.get_heatmap(&query.unwrap_or_default())).await
+}
+
+// E.g. this month against the same month last year.
+#[tauri::command]
+async fn compare_heatmaps(
+    state: tauri::State<'_, Arc<Database>>,
+    first: StatsQuery,
+    second: StatsQuery,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<HeatmapComparison> {
+    auth.require(Role::Supervisor)?;
+    state.run(move |db| db.compare_heatmaps(&first, &second)).await
+}
+
+// For a kiosk running on this machine.
+#[tauri::command]
+async fn issue_ticket(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    service: Option<String>,
+) -> QmsResult<IssuedTicket> {
+    // Open to a locked app; attributed to whoever is logged in, if anyone.
+    let actor = match auth.current() {
+        Some(session) => Actor::staff(&session),
+        None => Actor::anonymous("admin app"),
+    };
+    state
+        .run(move |db| db.issue_ticket(service, &actor.audit("ticket_issued")))
+        .await
+}
+
+// Waiting time, service time and no-shows per service, against the SLA targets.
+#[tauri::command]
+async fn get_waiting_metrics(
+    state: tauri::State<'_, Arc<Database>>,
+    query: Option<StatsQuery>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<WaitingMetrics> {
+    auth.require(Role::Supervisor)?;
+    state.run(move |db| db.get_waiting_metrics(&query.unwrap_or_default())).await
+}
+
+#[tauri::command]
+async fn get_sla_settings(state: tauri::State<'_, Arc<Database>>) -> QmsResult<SlaSettings> {
+    state.run(|db| db.get_setting(settings::SLA)).await
+}
+
+#[tauri::command]
+async fn set_sla_settings(
+    state: tauri::State<'_, Arc<Database>>,
+    settings: SlaSettings,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    settings.validate()?;
+    state
+        .run(move |db| db.set_setting(settings::SLA, &settings, &actor.audit("sla_settings_changed")))
+        .await
+}
+
+// Puts a desk on break, as its `/close` button would.
+#[tauri::command]
+async fn close_desk(
+    state: tauri::State<'_, Arc<Database>>,
+    desk_name: String,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<String> {
+    let actor = Actor::staff(&auth.require(Role::Operator)?);
+    state
+        .run(move |db| db.close_desk(desk_name, &actor.audit("desk_closed")))
+        .await
+}
+
+/**
+ * TTS ******************************************************************
+ */
+
+#[tauri::command]
+fn get_announcer_status(announcer: tauri::State<Announcer>) -> AnnouncerStatus {
+    announcer.status()
+}
+
+#[tauri::command]
+async fn get_tts_settings(state: tauri::State<'_, Arc<Database>>) -> QmsResult<TtsSettings> {
+    state.run(|db| db.get_setting(settings::TTS)).await
+}
+
+#[tauri::command]
+async fn set_tts_settings(
+    state: tauri::State<'_, Arc<Database>>,
+    announcer: tauri::State<'_, Announcer>,
+    voice_packs: tauri::State<'_, VoicePacks>,
+    settings: TtsSettings,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    settings.validate()?;
+    for id in settings.languages.iter().filter_map(|entry| entry.voice_pack.as_deref()) {
+        if voice_packs.get(id).is_none() {
+            return Err(QmsError::NotFound(format!("Voice pack '{}' not found", id)));
+        }
+    }
+
+    let saved = settings.clone();
+    state
+        .run(move |db| db.set_setting(settings::TTS, &saved, &actor.audit("tts_settings_changed")))
+        .await?;
+    announcer.configure(settings);
+    Ok(())
+}
+
+#[tauri::command]
+fn list_voice_packs(voice_packs: tauri::State<VoicePacks>) -> Vec<VoicePackInfo> {
+    voice_packs.list()
+}
+
+// Picks up packs copied into the folder (or re-recorded) without a restart.
+#[tauri::command]
+fn reload_voice_packs(
+    voice_packs: tauri::State<VoicePacks>,
+    auth: tauri::State<AuthState>,
+) -> QmsResult<Vec<VoicePackInfo>> {
+    auth.require(Role::Admin)?;
+    Ok(voice_packs.reload())
+}
+
+#[tauri::command]
+fn list_voices(announcer: tauri::State<Announcer>) -> Vec<VoiceInfo> {
+    announcer.voices()
+}
+
+// Speaks a sample call. Without `settings`, the saved ones are used, so the settings
+// page can let the user try a voice before saving it.
+#[tauri::command]
+async fn preview_tts(
+    state: tauri::State<'_, Arc<Database>>,
+    announcer: tauri::State<'_, Announcer>,
+    settings: Option<TtsSettings>,
+    ticket: Option<i32>,
+    desk: Option<String>,
+    service: Option<String>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    auth.require(Role::Operator)?;
+    let settings = match settings {
+        Some(settings) => settings,
+        None => state.run(|db| db.get_setting(settings::TTS)).await?,
+    };
+    settings.validate()?;
+
+    announcer.preview(
+        Announcement::Call {
+            ticket: ticket.unwrap_or(12),
+            desk: desk.unwrap_or_else(|| "1".to_string()),
+            service,
+        },
+        Some(settings),
+        None,
+    );
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_sound_settings(state: tauri::State<'_, Arc<Database>>) -> QmsResult<SoundSettings> {
+    state.run(|db| db.get_setting(settings::SOUNDS)).await
+}
+
+#[tauri::command]
+async fn set_sound_settings(
+    state: tauri::State<'_, Arc<Database>>,
+    announcer: tauri::State<'_, Announcer>,
+    sounds: SoundSettings,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    sounds.validate()?;
+
+    let saved = sounds.clone();
+    state
+        .run(move |db| db.set_setting(settings::SOUNDS, &saved, &actor.audit("sound_settings_changed")))
+        .await?;
+    announcer.configure_sounds(sounds);
+    Ok(())
+}
+
+// Plays the sound of one event, with unsaved settings when given.
+#[tauri::command]
+fn preview_sound(
+    announcer: tauri::State<Announcer>,
+    event: SoundEvent,
+    sounds: Option<SoundSettings>,
+    auth: tauri::State<AuthState>,
+) -> QmsResult<()> {
+    auth.require(Role::Operator)?;
+    if let Some(sounds) = &sounds {
+        sounds.validate()?;
+    }
+    announcer.preview(Announcement::Chime(event), None, sounds);
+    Ok(())
+}
+
+// Speaks a free-text message from the admin PC, e.g. an urgent instruction.
+#[tauri::command]
+async fn announce_message(
+    state: tauri::State<'_, Arc<Database>>,
+    announcer: tauri::State<'_, Announcer>,
+    text: String,
+    urgent: bool,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Operator)?);
+    if text.trim().is_empty() {
+        return Err(QmsError::Validation("Message cannot be empty".to_string()));
+    }
+    let details = serde_json::json!({ "text": text, "urgent": urgent });
+    announcer.announce(Announcement::Message { text, urgent });
+    state
+        .run(move |db| {
+            db.audit(&actor, "message_announced", None, Some(details))?;
+            Ok(())
+        })
+        .await
+}
+
+/**
+ * EMERGENCY ************************************************************
+ * Tauri commands only: buttons and screens have no way to start or clear it.
+ */
+
+#[tauri::command]
+fn get_emergency(emergency: tauri::State<EmergencyState>) -> Option<Emergency> {
+    emergency.current()
+}
+
+// Starting again while active replaces the message.
+#[tauri::command]
+async fn start_emergency(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Database>>,
+    emergency: tauri::State<'_, EmergencyState>,
+    announcer: tauri::State<'_, Announcer>,
+    tx: tauri::State<'_, broadcast::Sender<ServerEvent>>,
+    message: String,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Emergency> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    let message = message.trim().to_string();
+    if message.is_empty() {
+        return Err(QmsError::Validation("Emergency message cannot be empty".to_string()));
+    }
+
+    let started = Emergency::new(message);
+    emergency.set(Some(started.clone()));
+    println!("🚨 EMERGENCY: {}", started.message);
+    announcer.alarm(Some(started.message.clone()));
+    let _ = tx.send(ServerEvent::Emergency(started.clone()));
+    let _ = app_handle.emit("emergency", Some(&started));
+
+    let details = audit::value(&started);
+    if let Err(e) = state
+        .run(move |db| {
+            db.audit(&actor, "emergency_started", None, details)?;
+            Ok(())
+        })
+        .await
+    {
+        // The screens are taken over even if the log cannot be written.
+        eprintln!("❌ Cannot log start of emergency: {}", e);
+    }
+    Ok(started)
+}
+
+#[tauri::command]
+async fn clear_emergency(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Database>>,
+    emergency: tauri::State<'_, EmergencyState>,
+    announcer: tauri::State<'_, Announcer>,
+    tx: tauri::State<'_, broadcast::Sender<ServerEvent>>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    let Some(cleared) = emergency.set(None) else {
+        return Err(QmsError::NotFound("No emergency in progress".to_string()));
+    };
+
+    let details = audit::value(&cleared);
+    if let Err(e) = state
+        .run(move |db| {
+            db.audit(&actor, "emergency_cleared", details, None)?;
+            Ok(())
+        })
+        .await
+    {
+        // Screens must be released even if the log cannot be written.
+        eprintln!("❌ Cannot log end of emergency: {}", e);
+    }
+
+    println!("✅ Emergency cleared");
+    announcer.alarm(None);
+    let _ = tx.send(ServerEvent::EmergencyCleared);
+    let _ = app_handle.emit("emergency", None::<Emergency>);
+    Ok(())
+}
+
+/**
+ * PLAYLIST *************************************************************
+ */
+
+#[tauri::command]
+async fn get_playlist(state: tauri::State<'_, Arc<Database>>) -> QmsResult<Vec<Slide>> {
+    state.run(|db| db.get_playlist()).await
+}
+
+// `path` is a file picked on this machine; it is copied into the media folder.
+#[tauri::command]
+async fn upload_slide(
+    state: tauri::State<'_, Arc<Database>>,
+    media: tauri::State<'_, MediaLibrary>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    path: String,
+    duration_seconds: Option<u32>,
+    window: Option<TimeWindow>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Slide> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let library = media.inner().clone();
+    let (file_name, kind) = tokio::task::spawn_blocking(move || library.import(std::path::Path::new(&path)))
+        .await
+        .map_err(|e| QmsError::Internal(e.to_string()))??;
+
+    let name = file_name.clone();
+    let added = state
+        .run(move |db| {
+            db.add_slide(
+                kind,
+                Some(name),
+                None,
+                duration_seconds.unwrap_or(10),
+                window.unwrap_or_default(),
+                &actor.audit("slide_added"),
+            )
+        })
+        .await;
+    match added {
+        Ok(slide) => {
+            scheduler.refresh();
+            Ok(slide)
+        }
+        Err(e) => {
+            media.remove(&file_name);
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+async fn add_text_slide(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    text: String,
+    duration_seconds: Option<u32>,
+    window: Option<TimeWindow>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Slide> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let slide = state
+        .run(move |db| {
+            db.add_slide(
+                SlideKind::Text,
+                None,
+                Some(text),
+                duration_seconds.unwrap_or(10),
+                window.unwrap_or_default(),
+                &actor.audit("slide_added"),
+            )
+        })
+        .await?;
+    scheduler.refresh();
+    Ok(slide)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn update_slide(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    id: i32,
+    duration_seconds: u32,
+    window: Option<TimeWindow>,
+    active: bool,
+    text: Option<String>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Slide> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let slide = state
+        .run(move |db| {
+            db.update_slide(
+                id,
+                duration_seconds,
+                window.unwrap_or_default(),
+                active,
+                text,
+                &actor.audit("slide_updated"),
+            )
+        })
+        .await?;
+    scheduler.refresh();
+    Ok(slide)
+}
+
+#[tauri::command]
+async fn reorder_slides(
+    state: tauri::State<'_, Arc<Database>>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    ids: Vec<i32>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Vec<Slide>> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let playlist = state
+        .run(move |db| {
+            db.reorder_slides(&ids, &actor.audit("slides_reordered"))?;
+            db.get_playlist()
+        })
+        .await?;
+    scheduler.refresh();
+    Ok(playlist)
+}
+
+#[tauri::command]
+async fn delete_slide(
+    state: tauri::State<'_, Arc<Database>>,
+    media: tauri::State<'_, MediaLibrary>,
+    scheduler: tauri::State<'_, AnnonceScheduler>,
+    id: i32,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<()> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let slide = state
+        .run(move |db| db.delete_slide(id, &actor.audit("slide_deleted")))
+        .await?;
+    if let Some(file_name) = slide.file_name() {
+        media.remove(file_name);
+    }
+    scheduler.refresh();
+    Ok(())
+}
+
+/**
+ * STAFF ****************************************************************
+ */
+
+#[tauri::command]
+async fn get_staff(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Vec<Staff>> {
+    auth.require(Role::Supervisor)?;
+    state.run(|db| db.get_staff()).await
+}
+
+// Without a PIN the operator can only be logged in from here. `role` defaults to
+// operator; only accounts with a password or PIN can log in to this app.
+#[tauri::command]
+async fn add_staff(
+    state: tauri::State<'_, Arc<Database>>,
+    name: String,
+    role: Option<Role>,
+    pin: Option<String>,
+    password: Option<String>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Staff> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    state
+        .run(move |db| db.add_staff(name, role.unwrap_or(Role::Operator), pin, password, &actor.audit("staff_added")))
+        .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn update_staff(
+    state: tauri::State<'_, Arc<Database>>,
+    id: i32,
+    name: String,
+    role: Role,
+    active: bool,
+    pin: Option<String>,
+    password: Option<String>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Staff> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    let staff = state
+        .run(move |db| db.update_staff(id, name, role, active, pin, password, &actor.audit("staff_updated")))
+        .await?;
+    // When it is the logged-in account: deactivating logs it out, a new role applies now.
+    if let Some(mut session) = auth.current().filter(|session| session.staff_id == id) {
+        session.role = role;
+        auth.set(active.then_some(session));
+    }
+    Ok(staff)
+}
+
+// Replaces whoever was logged in at the desk.
+#[tauri::command]
+async fn login_operator(
+    state: tauri::State<'_, Arc<Database>>,
+    desk_name: String,
+    staff_id: i32,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<DeskSession> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    state
+        .run(move |db| db.login_operator(&desk_name, staff_id, &actor.audit("operator_logged_in")))
+        .await
+}
+
+#[tauri::command]
+async fn logout_operator(
+    state: tauri::State<'_, Arc<Database>>,
+    desk_name: String,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Option<DeskSession>> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    state
+        .run(move |db| db.logout_operator(&desk_name, &actor.audit("operator_logged_out")))
+        .await
+}
+
+#[tauri::command]
+async fn get_desk_sessions(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<Vec<DeskSession>> {
+    auth.require(Role::Supervisor)?;
+    state.run(|db| db.get_desk_sessions()).await
+}
+
+/**
+ * AUTH *****************************************************************
+ * Who is using this app. Left open: what the display page and a kiosk need
+ * (counter state, history, annonces, playlist, emergency, issuing tickets).
+ */
+
+#[tauri::command]
+async fn get_auth_status(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+) -> QmsResult<AuthStatus> {
+    let session = auth.current();
+    state.run(move |db| db.auth_status(session)).await
+}
+
+// With the account's password, or an operator's PIN. Repeated failures for a name
+// lock it out for a while.
+#[tauri::command]
+async fn login(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    logins: tauri::State<'_, LoginThrottle>,
+    name: String,
+    secret: String,
+) -> QmsResult<Session> {
+    let throttle_key = format!("name:{}", name.trim().to_lowercase());
+    logins.check(&throttle_key)?;
+    let logins = logins.inner().clone();
+    let session = state
+        .run(move |db| {
+            let session = db.authenticate(&name, &secret);
+            match &session {
+                Ok(session) => {
+                    logins.succeeded(&throttle_key);
+                    db.audit(&Actor::staff(session), "logged_in", None, None)?;
+                }
+                Err(QmsError::Unauthorized(_)) => {
+                    logins.failed(&throttle_key);
+                    db.audit(
+                        &Actor::anonymous("admin app"),
+                        "login_failed",
+                        None,
+                        Some(serde_json::json!({ "name": name })),
+                    )?;
+                }
+                Err(_) => {}
+            }
+            session
+        })
+        .await?;
+    println!("🔓 {} logged in as {}", session.name, session.role.as_str());
+    auth.set(Some(session.clone()));
+    Ok(session)
+}
+
+#[tauri::command]
+async fn logout(state: tauri::State<'_, Arc<Database>>, auth: tauri::State<'_, AuthState>) -> QmsResult<()> {
+    let Some(session) = auth.current() else {
+        return Ok(());
+    };
+    auth.set(None);
+    println!("🔒 {} logged out", session.name);
+    state
+        .run(move |db| {
+            db.audit(&Actor::staff(&session), "logged_out", None, None)?;
+            Ok(())
+        })
+        .await
+}
+
+// First start only: nothing else can be done until an admin exists.
+#[tauri::command]
+async fn create_first_admin(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    name: String,
+    password: String,
+) -> QmsResult<Session> {
+    let session = state
+        .run(move |db| db.create_first_admin(&name, &password))
+        .await?;
+    auth.set(Some(session.clone()));
+    Ok(session)
+}
+
+/**
+ * AUDIT ****************************************************************
+ * Read-only: entries are written by the commands and endpoints themselves.
+ */
+
+#[tauri::command]
+async fn query_audit_log(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    query: Option<AuditQuery>,
+) -> QmsResult<AuditPage> {
+    auth.require(Role::Admin)?;
+    state.run(move |db| db.query_audit_log(&query.unwrap_or_default())).await
+}
+
+// Writes every matching entry to `path` (a file picked on this machine). Returns how
+// many were written.
+#[tauri::command]
+async fn export_audit_log(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    query: Option<AuditQuery>,
+    path: String,
+    format: ExportFormat,
+) -> QmsResult<u64> {
+    let actor = Actor::staff(&auth.require(Role::Admin)?);
+    state
+        .run(move |db| {
+            let query = query.unwrap_or_default();
+            let rows = db.export_audit_log(&query, std::path::Path::new(&path), format)?;
+            db.audit(&actor, "audit_log_exported", None, Some(serde_json::json!({ "path": path, "rows": rows })))?;
+            Ok(rows)
+        })
+        .await
+}
+
+/**
+ * EXPORT ***************************************************************
+ * To a file picked on this machine, with the same filters as `query_history`.
+ * Each returns how many rows were written.
+ */
+
+// Every matching ticket, from issue to end; `offset` and `limit` are ignored.
+#[tauri::command]
+async fn export_history(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    query: Option<HistoryQuery>,
+    path: String,
+    format: ExportFormat,
+) -> QmsResult<u64> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    state
+        .run(move |db| {
+            let rows = db.export_history(&query.unwrap_or_default(), std::path::Path::new(&path), format)?;
+            db.audit(&actor, "history_exported", None, Some(serde_json::json!({ "path": path, "rows": rows })))?;
+            Ok(rows)
+        })
+        .await
+}
+
+// Totals for the site and per desk, service and operator over the query's dates.
+#[tauri::command]
+async fn export_stats(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    query: Option<HistoryQuery>,
+    path: String,
+    format: ExportFormat,
+) -> QmsResult<u64> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    state
+        .run(move |db| {
+            let query = StatsQuery::from(&query.unwrap_or_default());
+            let rows = db.export_stats(&query, std::path::Path::new(&path), format)?;
+            db.audit(&actor, "stats_exported", None, Some(serde_json::json!({ "path": path, "rows": rows })))?;
+            Ok(rows)
+        })
+        .await
+}
+
+/**
+ * REPORTS **************************************************************
+ * Also generated after each counter reset, for the days (and week) it closes.
+ */
+
+// The report of the day or week that includes `date` ("YYYY-MM-DD", default today),
+// saved as HTML and PDF in the reports folder. Replaces an earlier one for the same period.
+#[tauri::command]
+async fn generate_report(
+    state: tauri::State<'_, Arc<Database>>,
+    auth: tauri::State<'_, AuthState>,
+    reports: tauri::State<'_, Reports>,
+    period: ReportPeriod,
+    date: Option<String>,
+) -> QmsResult<ReportFiles> {
+    let actor = Actor::staff(&auth.require(Role::Supervisor)?);
+    let date = schedule::parse_date(&date)?.unwrap_or_else(|| schedule::now().date());
+    let (from, to) = period.range(date);
+    let reports = reports.inner().clone();
+    state
+        .run(move |db| {
+            let files = db.generate_report(&reports, period, from, to)?;
+            db.audit(&actor, "report_generated", None, audit::value(&files))?;
+            Ok(files)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db() -> (tempfile::TempDir, Arc<Database>) {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let db = Database::init(&dir.path().join("qms.db")).expect("init database");
+        (dir, Arc::new(db))
+    }
+
+    // Parallel `/next` presses on several desks, through the HTTP server: every number
+    // is handed out exactly once.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_calls_get_consecutive_numbers() {
+        use tower::ServiceExt;
+
+        const DESKS: usize = 8;
+        const CALLS_PER_DESK: usize = 50;
+        let (dir, db) = temp_db();
+        let actor = Actor::anonymous("test");
+        let tokens: Vec<String> = (1..=DESKS)
+            .map(|desk| {
+                let device = db.register_device(format!("Guichet {}", desk), &actor.audit("device_registered"));
+                device.unwrap().expect("new device").token
+            })
+            .collect();
+
+        let (calls, _pending_calls) = mpsc::unbounded_channel();
+        let app = router(Arc::new(AppState {
+            db: db.clone(),
+            app_handle: None,
+            tx: broadcast::channel(16).0,
+            announcer: Announcer::spawn(
+                None,
+                TtsSettings::default(),
+                SoundSettings::default(),
+                VoicePacks::open(dir.path().join("voice_packs")),
+            ),
+            emergency: EmergencyState::default(),
+            media: None,
+            clips: None,
+            logins: LoginThrottle::default(),
+            calls,
+        }));
+
+        let presses = tokens.iter().flat_map(|token| std::iter::repeat(token).take(CALLS_PER_DESK));
+        let presses: Vec<_> = presses
+            .map(|token| {
+                let request = axum::http::Request::post("/next")
+                    .header("Authorization", format!("Bearer {}", token))
+                    .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+                    .body(axum::body::Body::empty())
+                    .unwrap();
+                tokio::spawn(app.clone().oneshot(request))
+            })
+            .collect();
+
+        let mut answered = Vec::new();
+        for press in presses {
+            let response = press.await.unwrap().unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            answered.push(json["compteur"].as_i64().unwrap() as i32);
+        }
+        answered.sort_unstable();
+        let expected: Vec<i32> = (1..=(DESKS * CALLS_PER_DESK) as i32).collect();
+        assert_eq!(answered, expected);
+
+        let conn = db.reader().expect("reader");
+        let mut stmt = conn
+            .prepare("SELECT ticket_number FROM historique WHERE ticket_number >= 0 ORDER BY ticket_number")
+            .unwrap();
+        let numbers: Vec<i32> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(numbers, expected);
+        assert_eq!(db.get_current().unwrap().compteur, (DESKS * CALLS_PER_DESK) as i32);
+    }
+
+    fn status_of(db: &Database, ticket: i32) -> (String, bool) {
+        let conn = db.reader().unwrap();
+        conn.query_row(
+            "SELECT status, ended_at IS NOT NULL FROM historique WHERE ticket_number = ?1",
+            params![ticket],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn recall_keeps_ended_tickets() {
+        let (_dir, db) = temp_db();
+        let actor = Actor::anonymous("test");
+        let call = actor.audit("ticket_called");
+        let recall = actor.audit("ticket_recalled");
+        let end = actor.audit("ticket_ended");
+
+        let open = db.incrementer("Guichet 1", None, &call).unwrap().compteur;
+        assert_eq!(db.recall_ticket("Guichet 1", &recall).unwrap(), Some(open));
+        assert_eq!(status_of(&db, open), ("recalled".to_string(), false));
+
+        let served = db.incrementer("Guichet 1", None, &call).unwrap().compteur;
+        db.end_ticket("Guichet 1", TicketStatus::Served, &end).unwrap();
+        assert_eq!(db.recall_ticket("Guichet 1", &recall).unwrap(), None);
+        assert_eq!(status_of(&db, served), ("served".to_string(), true));
+
+        let missed = db.incrementer("Guichet 2", None, &call).unwrap().compteur;
+        db.end_ticket("Guichet 2", TicketStatus::NoShow, &end).unwrap();
+        assert_eq!(db.recall_ticket("Guichet 2", &recall).unwrap(), None);
+        assert_eq!(status_of(&db, missed), ("no_show".to_string(), true));
+    }
+
+    #[test]
+    fn no_shows_are_not_served() {
+        let (_dir, db) = temp_db();
+        let actor = Actor::anonymous("test");
+        let call = actor.audit("ticket_called");
+        let end = actor.audit("ticket_ended");
+
+        db.incrementer("Guichet 1", None, &call).unwrap();
+        db.end_ticket("Guichet 1", TicketStatus::Served, &end).unwrap();
+        db.incrementer("Guichet 1", None, &call).unwrap();
+        db.end_ticket("Guichet 1", TicketStatus::NoShow, &end).unwrap();
+        db.incrementer("Guichet 1", None, &call).unwrap();
+
+        let stats = db.get_stats_range(&StatsQuery::default()).unwrap();
+        let desk = &stats.desks[0].stats;
+        assert_eq!((desk.served, desk.no_shows), (2, 1));
+    }
+
+    // A call whose audit entry cannot be written is not made at all.
+    #[test]
+    fn failed_audit_rolls_back_the_call() {
+        let (_dir, db) = temp_db();
+        let actor = Actor::anonymous("test");
+        db.incrementer("Guichet 1", None, &actor.audit("ticket_called")).unwrap();
+        db.writer().execute_batch("DROP TABLE audit_log").unwrap();
+
+        assert!(db.incrementer("Guichet 1", None, &actor.audit("ticket_called")).is_err());
+        assert_eq!(db.get_current().unwrap().compteur, 1);
+        let calls: i32 = db
+            .reader()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM historique WHERE ticket_number >= 0", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(calls, 1);
+    }
 }
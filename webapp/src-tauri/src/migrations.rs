@@ -0,0 +1,450 @@
+use rusqlite::{params, Connection};
+use std::fmt;
+
+// A schema change, applied once and recorded in `schema_version`.
+// Migrations are never edited once released: add a new one instead.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+// Ordered list of every migration. Version 1 reproduces the original schema with
+// `IF NOT EXISTS` so installs created before versioning are adopted as-is.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: "
+        CREATE TABLE IF NOT EXISTS etat_courant (
+            id INTEGER PRIMARY KEY,
+            valeur_compteur INTEGER NOT NULL,
+            dernier_guichet TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS devices (
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            token TEXT UNIQUE NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS annonces (
+            id INTEGER PRIMARY KEY,
+            message TEXT NOT NULL,
+            active BOOLEAN DEFAULT 1
+        );
+
+        INSERT OR IGNORE INTO etat_courant (id, valeur_compteur, dernier_guichet) VALUES (1, 0, 'None');
+
+        CREATE TABLE IF NOT EXISTS historique (
+            id INTEGER PRIMARY KEY,
+            ticket_number INTEGER NOT NULL,
+            desk_name TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+    ",
+    },
+    Migration {
+        version: 2,
+        name: "settings_and_device_service",
+        sql: "
+        -- Free-form app settings, stored as JSON per key.
+        CREATE TABLE settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+
+        ALTER TABLE devices ADD COLUMN service TEXT;
+    ",
+    },
+    Migration {
+        version: 3,
+        name: "annonce_schedule",
+        sql: "
+        -- Dates are YYYY-MM-DD and times HH:MM, local time. NULL means no limit.
+        ALTER TABLE annonces ADD COLUMN start_date TEXT;
+        ALTER TABLE annonces ADD COLUMN end_date TEXT;
+        ALTER TABLE annonces ADD COLUMN time_start TEXT;
+        ALTER TABLE annonces ADD COLUMN time_end TEXT;
+        -- Bit 0 = Monday ... bit 6 = Sunday
+        ALTER TABLE annonces ADD COLUMN days_of_week INTEGER NOT NULL DEFAULT 127;
+        ALTER TABLE annonces ADD COLUMN display_seconds INTEGER NOT NULL DEFAULT 10;
+        ALTER TABLE annonces ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+    ",
+    },
+    Migration {
+        version: 4,
+        name: "annonce_speech",
+        sql: "
+        ALTER TABLE annonces ADD COLUMN audible BOOLEAN NOT NULL DEFAULT 0;
+        -- Minutes between readings while on air; NULL reads it once.
+        ALTER TABLE annonces ADD COLUMN repeat_minutes INTEGER;
+        -- Comma-separated HH:MM times at which it is read as well.
+        ALTER TABLE annonces ADD COLUMN speak_times TEXT NOT NULL DEFAULT '';
+    ",
+    },
+    Migration {
+        version: 5,
+        name: "audit_log",
+        sql: "
+        CREATE TABLE audit_log (
+            id INTEGER PRIMARY KEY,
+            action TEXT NOT NULL,
+            details TEXT NOT NULL DEFAULT '{}',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+    ",
+    },
+    Migration {
+        version: 6,
+        name: "playlist_slides",
+        sql: "
+        -- Idle-screen playlist. Media files live in the app data dir under media/.
+        CREATE TABLE slides (
+            id INTEGER PRIMARY KEY,
+            kind TEXT NOT NULL CHECK (kind IN ('image', 'video', 'text')),
+            file_name TEXT,
+            text TEXT,
+            duration_seconds INTEGER NOT NULL DEFAULT 10,
+            position INTEGER NOT NULL,
+            active BOOLEAN NOT NULL DEFAULT 1,
+            start_date TEXT,
+            end_date TEXT,
+            time_start TEXT,
+            time_end TEXT,
+            days_of_week INTEGER NOT NULL DEFAULT 127,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+    ",
+    },
+    Migration {
+        version: 7,
+        name: "annonce_targets",
+        sql: "
+        -- Free-form group, e.g. 'loans-area'. Annonces can target it.
+        ALTER TABLE devices ADD COLUMN group_name TEXT;
+        -- JSON {devices, groups, services}; '{}' shows the annonce on every screen.
+        ALTER TABLE annonces ADD COLUMN targets TEXT NOT NULL DEFAULT '{}';
+    ",
+    },
+    Migration {
+        version: 8,
+        name: "history_service_and_status",
+        sql: "
+        -- The desk's service at call time, so history survives a desk changing service.
+        ALTER TABLE historique ADD COLUMN service TEXT;
+        -- 'called', 'recalled', 'served' or 'no_show'. Meaningless on marker rows (-1, -2).
+        ALTER TABLE historique ADD COLUMN status TEXT NOT NULL DEFAULT 'called';
+
+        UPDATE historique SET service = (
+            SELECT service FROM devices WHERE devices.name = historique.desk_name
+        );
+        CREATE INDEX idx_historique_desk ON historique (desk_name);
+        CREATE INDEX idx_historique_created_at ON historique (created_at);
+    ",
+    },
+    Migration {
+        version: 9,
+        name: "history_ended_at",
+        sql: "
+        -- Set when the desk marks the ticket done; NULL means it ran until the desk's next row.
+        ALTER TABLE historique ADD COLUMN ended_at DATETIME;
+    ",
+    },
+    Migration {
+        version: 10,
+        name: "ticket_issuance",
+        sql: "
+        -- Tickets handed out by a kiosk. Numbers restart after each reset; `reset_id`
+        -- is the id of the -2 row they follow (0 before the first reset).
+        CREATE TABLE issued_tickets (
+            id INTEGER PRIMARY KEY,
+            ticket_number INTEGER NOT NULL,
+            service TEXT,
+            reset_id INTEGER NOT NULL,
+            issued_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX idx_issued_tickets_number ON issued_tickets (reset_id, ticket_number);
+        CREATE INDEX idx_issued_tickets_issued_at ON issued_tickets (issued_at);
+
+        -- Copied from `issued_tickets` when the ticket is called.
+        ALTER TABLE historique ADD COLUMN issued_at DATETIME;
+    ",
+    },
+    Migration {
+        version: 11,
+        name: "staff",
+        sql: "
+        CREATE TABLE staff (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            -- Argon2 hash; NULL means the operator can only be logged in from the admin app.
+            pin_hash TEXT,
+            active BOOLEAN NOT NULL DEFAULT 1,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- Who works at which desk. An open session has no `ended_at`.
+        CREATE TABLE desk_sessions (
+            id INTEGER PRIMARY KEY,
+            desk_name TEXT NOT NULL,
+            staff_id INTEGER NOT NULL REFERENCES staff(id),
+            started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            ended_at DATETIME
+        );
+        CREATE INDEX idx_desk_sessions_open ON desk_sessions (desk_name, ended_at);
+
+        -- The operator logged in at the desk when the ticket was called.
+        ALTER TABLE historique ADD COLUMN operator_id INTEGER REFERENCES staff(id);
+    ",
+    },
+    Migration {
+        version: 12,
+        name: "staff_roles",
+        sql: "
+        -- What the account may do in the admin app: operator < supervisor < admin.
+        ALTER TABLE staff ADD COLUMN role TEXT NOT NULL DEFAULT 'operator'
+            CHECK (role IN ('operator', 'supervisor', 'admin'));
+        -- Argon2 hash, for logging in to the admin app.
+        ALTER TABLE staff ADD COLUMN password_hash TEXT;
+    ",
+    },
+    Migration {
+        version: 13,
+        name: "audit_log_actor",
+        sql: "
+        -- Who did it: the logged-in staff member, or the device that made the request.
+        ALTER TABLE audit_log ADD COLUMN actor TEXT;
+        ALTER TABLE audit_log ADD COLUMN staff_id INTEGER REFERENCES staff(id);
+        -- 'admin app', or the IP address of the device.
+        ALTER TABLE audit_log ADD COLUMN source TEXT;
+        -- JSON of what was changed, as it was and as it became. NULL when it did not
+        -- exist before or after.
+        ALTER TABLE audit_log ADD COLUMN before_value TEXT;
+        ALTER TABLE audit_log ADD COLUMN after_value TEXT;
+
+        UPDATE audit_log SET after_value = details WHERE action = 'emergency_started';
+        UPDATE audit_log SET before_value = details WHERE action = 'emergency_cleared';
+        CREATE INDEX idx_audit_log_created_at ON audit_log (created_at);
+
+        -- Append-only: entries can be added, never changed or removed.
+        CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN
+            SELECT RAISE(ABORT, 'audit_log is append-only');
+        END;
+        CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN
+            SELECT RAISE(ABORT, 'audit_log is append-only');
+        END;
+    ",
+    },
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    // The DB was written by a newer version of the app.
+    TooNew { found: i32, latest: i32 },
+    // A migration failed; its transaction was rolled back.
+    Failed {
+        version: i32,
+        name: &'static str,
+        source: rusqlite::Error,
+    },
+    // Reading or creating `schema_version` itself failed.
+    Sql(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::TooNew { found, latest } => write!(
+                f,
+                "database schema version {} is newer than the latest known version {}",
+                found, latest
+            ),
+            MigrationError::Failed {
+                version,
+                name,
+                source,
+            } => write!(f, "migration {} ({}) failed: {}", version, name, source),
+            MigrationError::Sql(e) => write!(f, "schema_version error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Failed { source, .. } => Some(source),
+            MigrationError::Sql(e) => Some(e),
+            MigrationError::TooNew { .. } => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sql(e)
+    }
+}
+
+pub fn current_version(conn: &Connection) -> Result<i32, MigrationError> {
+    let version = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(version)
+}
+
+// Brings the schema up to date. Each migration runs in its own transaction
+// together with its `schema_version` row, so a failure leaves the DB at the
+// last version that fully succeeded.
+pub fn run(conn: &mut Connection) -> Result<i32, MigrationError> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply(conn: &mut Connection, migrations: &[Migration]) -> Result<i32, MigrationError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    let start = current_version(conn)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+
+    if start > latest {
+        return Err(MigrationError::TooNew {
+            found: start,
+            latest,
+        });
+    }
+
+    let mut version = start;
+    for migration in migrations.iter().filter(|m| m.version > start) {
+        let failed = |source| MigrationError::Failed {
+            version: migration.version,
+            name: migration.name,
+            source,
+        };
+
+        let tx = conn.transaction().map_err(failed)?;
+        tx.execute_batch(migration.sql).map_err(failed)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )
+        .map_err(failed)?;
+        tx.commit().map_err(failed)?;
+
+        println!("🗄️ Migration {} ({}) applied", migration.version, migration.name);
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latest() -> i32 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+            params![name],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fresh_database_gets_every_migration() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run(&mut conn).unwrap(), latest());
+        assert_eq!(current_version(&conn).unwrap(), latest());
+        assert!(table_exists(&conn, "audit_log"));
+
+        // Nothing left to do the second time.
+        assert_eq!(run(&mut conn).unwrap(), latest());
+    }
+
+    // Installs from before `schema_version` have the original tables and their data.
+    #[test]
+    fn baseline_database_is_adopted() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE etat_courant (id INTEGER PRIMARY KEY, valeur_compteur INTEGER NOT NULL,
+                                        dernier_guichet TEXT NOT NULL);
+             CREATE TABLE devices (id INTEGER PRIMARY KEY, name TEXT UNIQUE NOT NULL, token TEXT UNIQUE NOT NULL);
+             CREATE TABLE annonces (id INTEGER PRIMARY KEY, message TEXT NOT NULL, active BOOLEAN DEFAULT 1);
+             CREATE TABLE historique (id INTEGER PRIMARY KEY, ticket_number INTEGER NOT NULL,
+                                      desk_name TEXT NOT NULL, created_at DATETIME DEFAULT CURRENT_TIMESTAMP);
+             INSERT INTO etat_courant VALUES (1, 42, 'Guichet 3');
+             INSERT INTO devices (name, token) VALUES ('Guichet 3', 'abc');
+             INSERT INTO historique (ticket_number, desk_name) VALUES (42, 'Guichet 3');",
+        )
+        .unwrap();
+
+        assert_eq!(run(&mut conn).unwrap(), latest());
+        let counter: i32 = conn
+            .query_row("SELECT valeur_compteur FROM etat_courant WHERE id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(counter, 42);
+        let (service, status): (Option<String>, String) = conn
+            .query_row("SELECT service, status FROM historique WHERE ticket_number = 42", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((service, status.as_str()), (None, "called"));
+    }
+
+    #[test]
+    fn failed_migration_is_rolled_back() {
+        let migrations = [
+            Migration {
+                version: 1,
+                name: "first",
+                sql: "CREATE TABLE first (id INTEGER PRIMARY KEY);",
+            },
+            Migration {
+                version: 2,
+                name: "broken",
+                sql: "CREATE TABLE second (id INTEGER PRIMARY KEY);
+                      INSERT INTO missing (id) VALUES (1);",
+            },
+        ];
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        match apply(&mut conn, &migrations) {
+            Err(MigrationError::Failed { version, .. }) => assert_eq!(version, 2),
+            other => panic!("expected migration 2 to fail, got {:?}", other),
+        }
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert!(table_exists(&conn, "first"));
+        assert!(!table_exists(&conn, "second"));
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, 'from_the_future')",
+            params![latest() + 1],
+        )
+        .unwrap();
+
+        match run(&mut conn) {
+            Err(MigrationError::TooNew { found, latest: known }) => assert_eq!((found, known), (latest() + 1, latest())),
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+// Slides shown by idle screens between calls: images and videos copied into the
+// app data dir, and plain text. Played in `position` order, each for its duration
+// and only within its time window.
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+use crate::audit::{self, Audit};
+use crate::error::{QmsError, QmsResult};
+use crate::schedule::{self, TimeWindow};
+use crate::Database;
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "svg"];
+const VIDEO_EXTENSIONS: [&str; 3] = ["mp4", "webm", "ogv"];
+// Screens download every file; keep them reasonable.
+const MAX_MEDIA_BYTES: u64 = 200 * 1024 * 1024;
+
+const SLIDE_COLUMNS: &str = "id, kind, file_name, text, duration_seconds, position, active,
+                             start_date, end_date, time_start, time_end, days_of_week";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlideKind {
+    Image,
+    Video,
+    Text,
+}
+
+impl SlideKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            SlideKind::Image => "image",
+            SlideKind::Video => "video",
+            SlideKind::Text => "text",
+        }
+    }
+
+    fn from_column(kind: &str) -> SlideKind {
+        match kind {
+            "image" => SlideKind::Image,
+            "video" => SlideKind::Video,
+            _ => SlideKind::Text,
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<SlideKind> {
+        let extension = extension.to_ascii_lowercase();
+        if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(SlideKind::Image)
+        } else if VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            Some(SlideKind::Video)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Slide {
+    id: i32,
+    kind: SlideKind,
+    file_name: Option<String>,
+    // Where screens fetch the media from, relative to the server.
+    url: Option<String>,
+    text: Option<String>,
+    // Videos are cut after this too.
+    duration_seconds: u32,
+    position: i32,
+    active: bool,
+    #[serde(flatten)]
+    window: TimeWindow,
+    // Active and within its window right now.
+    visible: bool,
+}
+
+impl Slide {
+    fn from_row(row: &rusqlite::Row, now: chrono::NaiveDateTime) -> rusqlite::Result<Self> {
+        let file_name: Option<String> = row.get(2)?;
+        let active: bool = row.get(6)?;
+        let window = TimeWindow::from_row(row, 7)?;
+        Ok(Slide {
+            id: row.get(0)?,
+            kind: SlideKind::from_column(&row.get::<_, String>(1)?),
+            url: file_name.as_ref().map(|name| format!("/media/{}", name)),
+            file_name,
+            text: row.get(3)?,
+            duration_seconds: row.get(4)?,
+            position: row.get(5)?,
+            active,
+            visible: active && window.is_live(now),
+            window,
+        })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+}
+
+fn validate_duration(duration_seconds: u32) -> QmsResult<()> {
+    if !(1..=3600).contains(&duration_seconds) {
+        return Err(QmsError::Validation("Slide duration must be between 1 and 3600 seconds".to_string()));
+    }
+    Ok(())
+}
+
+// The `media/` folder. Files get a random name on import so screens can cache them
+// forever and nothing from the admin's disk layout leaks out.
+#[derive(Clone)]
+pub struct MediaLibrary {
+    dir: PathBuf,
+}
+
+impl MediaLibrary {
+    pub fn open(dir: PathBuf) -> QmsResult<Self> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| QmsError::Internal(format!("Cannot create {}: {}", dir.display(), e)))?;
+        Ok(MediaLibrary { dir })
+    }
+
+    // Copies an image or video into the library. Blocking.
+    pub fn import(&self, source: &Path) -> QmsResult<(String, SlideKind)> {
+        let extension = source
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let kind = SlideKind::from_extension(&extension).ok_or_else(|| {
+            QmsError::Validation(format!("Unsupported media file: {}", source.display()))
+        })?;
+
+        let metadata = fs::metadata(source)
+            .map_err(|_| QmsError::NotFound(format!("File not found: {}", source.display())))?;
+        if metadata.len() > MAX_MEDIA_BYTES {
+            return Err(QmsError::Validation(format!(
+                "{} is larger than {} MB",
+                source.display(),
+                MAX_MEDIA_BYTES / 1024 / 1024
+            )));
+        }
+
+        let file_name = format!("{}.{}", Uuid::new_v4(), extension);
+        fs::copy(source, self.dir.join(&file_name))
+            .map_err(|e| QmsError::Internal(format!("Cannot copy {}: {}", source.display(), e)))?;
+        Ok((file_name, kind))
+    }
+
+    // Resolves a requested file name, refusing anything that could leave the folder.
+    pub fn path_of(&self, name: &str) -> Option<PathBuf> {
+        let valid = !name.starts_with('.')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !valid {
+            return None;
+        }
+        let path = self.dir.join(name);
+        path.is_file().then_some(path)
+    }
+
+    pub fn remove(&self, name: &str) {
+        if let Some(path) = self.path_of(name) {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("❌ Cannot delete {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+impl Database {
+    pub fn get_playlist(&self) -> QmsResult<Vec<Slide>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM slides ORDER BY position, id",
+            SLIDE_COLUMNS
+        ))?;
+        let now = schedule::now();
+        let iter = stmt.query_map([], |row| Slide::from_row(row, now))?;
+        let slides = iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(slides)
+    }
+
+    // Appended at the end of the playlist.
+    pub fn add_slide(
+        &self,
+        kind: SlideKind,
+        file_name: Option<String>,
+        text: Option<String>,
+        duration_seconds: u32,
+        window: TimeWindow,
+        audit: &Audit,
+    ) -> QmsResult<Slide> {
+        validate_duration(duration_seconds)?;
+        window.validate()?;
+        if kind == SlideKind::Text && !text.as_deref().is_some_and(|t| !t.trim().is_empty()) {
+            return Err(QmsError::Validation("Text slide cannot be empty".to_string()));
+        }
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO slides (kind, file_name, text, duration_seconds, position,
+                                 start_date, end_date, time_start, time_end, days_of_week)
+             VALUES (?1, ?2, ?3, ?4, (SELECT COALESCE(MAX(position), 0) + 1 FROM slides),
+                     ?5, ?6, ?7, ?8, ?9)",
+            params![
+                kind.as_str(),
+                file_name,
+                text,
+                duration_seconds,
+                window.start_date,
+                window.end_date,
+                window.time_start,
+                window.time_end,
+                window.days_mask()
+            ],
+        )?;
+        let slide = slide_by_id(&tx, tx.last_insert_rowid() as i32)?;
+        audit.record(&tx, None, audit::value(&slide))?;
+        tx.commit()?;
+        Ok(slide)
+    }
+
+    // `text` is only changed on text slides, and only when given.
+    pub fn update_slide(
+        &self,
+        id: i32,
+        duration_seconds: u32,
+        window: TimeWindow,
+        active: bool,
+        text: Option<String>,
+        audit: &Audit,
+    ) -> QmsResult<Slide> {
+        validate_duration(duration_seconds)?;
+        window.validate()?;
+        if text.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return Err(QmsError::Validation("Text slide cannot be empty".to_string()));
+        }
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = slide_by_id(&tx, id)?;
+        tx.execute(
+            "UPDATE slides
+             SET duration_seconds = ?1, active = ?2, start_date = ?3, end_date = ?4,
+                 time_start = ?5, time_end = ?6, days_of_week = ?7,
+                 text = CASE WHEN kind = 'text' THEN COALESCE(?8, text) ELSE text END
+             WHERE id = ?9",
+            params![
+                duration_seconds,
+                active,
+                window.start_date,
+                window.end_date,
+                window.time_start,
+                window.time_end,
+                window.days_mask(),
+                text,
+                id
+            ],
+        )?;
+        let slide = slide_by_id(&tx, id)?;
+        audit.record(&tx, audit::value(&before), audit::value(&slide))?;
+        tx.commit()?;
+        Ok(slide)
+    }
+
+    // `ids` in their new order. Slides left out keep their relative order after them.
+    // The audit entry gets the order of ids before and after.
+    pub fn reorder_slides(&self, ids: &[i32], audit: &Audit) -> QmsResult<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = slide_order(&tx)?;
+
+        for (index, id) in ids.iter().enumerate() {
+            let updated = tx.execute(
+                "UPDATE slides SET position = ?1 WHERE id = ?2",
+                params![index as i32 + 1, id],
+            )?;
+            if updated == 0 {
+                return Err(QmsError::NotFound(format!("Slide {} not found", id)));
+            }
+        }
+        // Pushed behind the listed ones without changing their own order.
+        let mut stmt = tx.prepare(&format!(
+            "SELECT id FROM slides WHERE id NOT IN ({}) ORDER BY position, id",
+            vec!["?"; ids.len()].join(",")
+        ))?;
+        let rest = stmt
+            .query_map(rusqlite::params_from_iter(ids), |row| row.get::<_, i32>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(stmt);
+        for (index, id) in rest.iter().enumerate() {
+            tx.execute(
+                "UPDATE slides SET position = ?1 WHERE id = ?2",
+                params![ids.len() as i32 + index as i32 + 1, id],
+            )?;
+        }
+        audit.record(&tx, audit::value(&before), audit::value(&slide_order(&tx)?))?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Returns the deleted slide so its media file can be removed too.
+    pub fn delete_slide(&self, id: i32, audit: &Audit) -> QmsResult<Slide> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let slide = slide_by_id(&tx, id)?;
+        tx.execute("DELETE FROM slides WHERE id = ?1", params![id])?;
+        audit.record(&tx, audit::value(&slide), None)?;
+        tx.commit()?;
+        Ok(slide)
+    }
+}
+
+fn slide_order(conn: &Connection) -> QmsResult<Vec<i32>> {
+    let mut stmt = conn.prepare("SELECT id FROM slides ORDER BY position, id")?;
+    let ids = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+    Ok(ids)
+}
+
+fn slide_by_id(conn: &Connection, id: i32) -> QmsResult<Slide> {
+    conn.query_row(
+        &format!("SELECT {} FROM slides WHERE id = ?1", SLIDE_COLUMNS),
+        params![id],
+        |row| Slide::from_row(row, schedule::now()),
+    )
+    .optional()?
+    .ok_or_else(|| QmsError::NotFound(format!("Slide {} not found", id)))
+}
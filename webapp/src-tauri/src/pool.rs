@@ -0,0 +1,89 @@
+use rusqlite::{Connection, OpenFlags};
+use std::{
+    ops::Deref,
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::error::QmsResult;
+
+// How long a statement waits on a lock held by another connection before failing
+// with SQLITE_BUSY (surfaced as 503 by the HTTP layer).
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Idle read-only connections kept open. More can be opened under load, they are
+// simply closed instead of returned once the pool is full.
+const MAX_IDLE_READERS: usize = 4;
+
+// Read-only connections on the same file as the writer. With WAL enabled, readers
+// see the last committed state and never wait on `incrementer` & co.
+pub struct ReaderPool {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl ReaderPool {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ReaderPool {
+            path: path.into(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get(&self) -> QmsResult<PooledConn<'_>> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop();
+
+        let conn = match idle {
+            Some(conn) => conn,
+            None => self.open()?,
+        };
+
+        Ok(PooledConn {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+
+    fn open(&self) -> QmsResult<Connection> {
+        let conn = Connection::open_with_flags(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(conn)
+    }
+}
+
+// Borrowed reader, handed back to the pool on drop.
+pub struct PooledConn<'a> {
+    pool: &'a ReaderPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConn<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConn<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let mut idle = self
+                .pool
+                .idle
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if idle.len() < MAX_IDLE_READERS {
+                idle.push(conn);
+            }
+        }
+    }
+}
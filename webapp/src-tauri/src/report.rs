@@ -0,0 +1,576 @@
+// Printable end-of-day and end-of-week summaries: tickets issued and called per
+// service, waiting and service times, busiest hours, desk uptime and no-shows.
+// Saved as HTML and PDF in the `reports/` folder, after each counter reset for the
+// period it closes, or on demand.
+use chrono::{Datelike, Duration, NaiveDate};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{QmsError, QmsResult};
+use crate::schedule;
+use crate::stats::StatsQuery;
+use crate::Database;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+    Daily,
+    // Monday to Sunday.
+    Weekly,
+}
+
+impl ReportPeriod {
+    // The days of the report that includes `date`.
+    pub fn range(self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            ReportPeriod::Daily => (date, date),
+            ReportPeriod::Weekly => {
+                let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                (monday, monday + Duration::days(6))
+            }
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ReportPeriod::Daily => "daily",
+            ReportPeriod::Weekly => "weekly",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReportFiles {
+    html: String,
+    pdf: String,
+}
+
+// A row of the services table, or the totals.
+struct ServiceLine {
+    name: String,
+    issued: u32,
+    called: u32,
+    no_shows: u32,
+    avg_wait: Option<f64>,
+    avg_service: Option<f64>,
+}
+
+struct DeskLine {
+    name: String,
+    calls: u32,
+    // From the desk's first call to its last activity, each day, minus breaks.
+    uptime_minutes: f64,
+    break_minutes: f64,
+    idle_minutes: f64,
+}
+
+pub struct Report {
+    period: ReportPeriod,
+    from: NaiveDate,
+    to: NaiveDate,
+    generated_at: String,
+    total: ServiceLine,
+    services: Vec<ServiceLine>,
+    calls_by_hour: [u32; 24],
+    desks: Vec<DeskLine>,
+}
+
+// What both renderers print, in order.
+struct Section {
+    heading: &'static str,
+    lines: Vec<String>,
+    table: Option<(Vec<&'static str>, Vec<Vec<String>>)>,
+}
+
+impl Report {
+    fn title(&self) -> String {
+        match self.period {
+            ReportPeriod::Daily if self.from == self.to => format!("Daily report, {}", self.from),
+            ReportPeriod::Daily => format!("Daily report, {} to {}", self.from, self.to),
+            ReportPeriod::Weekly => format!(
+                "Weekly report, week {} of {} ({} to {})",
+                self.from.iso_week().week(),
+                self.from.iso_week().year(),
+                self.from,
+                self.to
+            ),
+        }
+    }
+
+    // Regenerating a period replaces its files.
+    fn file_stem(&self) -> String {
+        match self.period {
+            ReportPeriod::Daily if self.from == self.to => format!("daily-{}", self.from),
+            ReportPeriod::Daily => format!("daily-{}_{}", self.from, self.to),
+            ReportPeriod::Weekly => format!(
+                "weekly-{}-W{:02}",
+                self.from.iso_week().year(),
+                self.from.iso_week().week()
+            ),
+        }
+    }
+
+    // The three hours with the most calls, busiest first.
+    fn busiest_hours(&self) -> Vec<(usize, u32)> {
+        let mut hours: Vec<(usize, u32)> = self
+            .calls_by_hour
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, calls)| *calls > 0)
+            .collect();
+        hours.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hours.truncate(3);
+        hours
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let total = &self.total;
+        let served = total.called - total.no_shows;
+        let summary = Section {
+            heading: "Summary",
+            lines: vec![
+                format!("Tickets issued: {}", total.issued),
+                format!("Tickets called: {} (served {}, no-shows {})", total.called, served, total.no_shows),
+                format!("No-show rate: {}", percent(total.no_shows, total.called)),
+                format!("Average wait: {}", minutes(total.avg_wait)),
+                format!("Average service time: {}", minutes(total.avg_service)),
+            ],
+            table: None,
+        };
+
+        let services = Section {
+            heading: "Per service",
+            lines: if self.services.is_empty() {
+                vec!["No tickets.".to_string()]
+            } else {
+                Vec::new()
+            },
+            table: (!self.services.is_empty()).then(|| {
+                let rows = self
+                    .services
+                    .iter()
+                    .chain(std::iter::once(total))
+                    .map(|line| {
+                        vec![
+                            line.name.clone(),
+                            line.issued.to_string(),
+                            line.called.to_string(),
+                            (line.called - line.no_shows).to_string(),
+                            line.no_shows.to_string(),
+                            minutes(line.avg_wait),
+                            minutes(line.avg_service),
+                        ]
+                    })
+                    .collect();
+                (
+                    vec!["Service", "Issued", "Called", "Served", "No-shows", "Avg wait", "Avg service"],
+                    rows,
+                )
+            }),
+        };
+
+        let busiest = self.busiest_hours();
+        let hours = Section {
+            heading: "Busiest hours",
+            lines: if busiest.is_empty() {
+                vec!["No calls.".to_string()]
+            } else {
+                busiest
+                    .iter()
+                    .map(|(hour, calls)| format!("{:02}:00 - {:02}:00: {} calls", hour, hour + 1, calls))
+                    .collect()
+            },
+            table: (!busiest.is_empty()).then(|| {
+                let rows = self
+                    .calls_by_hour
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, calls)| **calls > 0)
+                    .map(|(hour, calls)| vec![format!("{:02}:00", hour), calls.to_string()])
+                    .collect();
+                (vec!["Hour", "Calls"], rows)
+            }),
+        };
+
+        let desks = Section {
+            heading: "Desks",
+            lines: if self.desks.is_empty() {
+                vec!["No desk activity.".to_string()]
+            } else {
+                Vec::new()
+            },
+            table: (!self.desks.is_empty()).then(|| {
+                let rows = self
+                    .desks
+                    .iter()
+                    .map(|desk| {
+                        vec![
+                            desk.name.clone(),
+                            desk.calls.to_string(),
+                            hours_minutes(desk.uptime_minutes),
+                            hours_minutes(desk.break_minutes),
+                            hours_minutes(desk.idle_minutes),
+                        ]
+                    })
+                    .collect();
+                (vec!["Desk", "Calls", "Uptime", "Breaks", "Idle"], rows)
+            }),
+        };
+
+        vec![summary, services, hours, desks]
+    }
+}
+
+fn minutes(value: Option<f64>) -> String {
+    match value {
+        Some(m) => format!("{:.1} min", m),
+        None => "-".to_string(),
+    }
+}
+
+fn hours_minutes(minutes: f64) -> String {
+    let total = minutes.max(0.0).round() as u64;
+    format!("{} h {:02}", total / 60, total % 60)
+}
+
+fn percent(part: u32, total: u32) -> String {
+    match total {
+        0 => "-".to_string(),
+        _ => format!("{:.1} %", part as f64 * 100.0 / total as f64),
+    }
+}
+
+impl Database {
+    // What the reset about to happen closes: the days the current session was used,
+    // and, on the first reset of a new week, the week of the previous reset.
+    pub fn closing_reports(&self) -> QmsResult<Vec<(ReportPeriod, NaiveDate, NaiveDate)>> {
+        let conn = self.reader()?;
+        let (first, last, previous_reset): (Option<String>, Option<String>, Option<String>) = conn.query_row(
+            "SELECT date(MIN(created_at), 'localtime'),
+                    date(MAX(COALESCE(ended_at, created_at)), 'localtime'),
+                    (SELECT date(created_at, 'localtime') FROM historique WHERE ticket_number = -2
+                     ORDER BY id DESC LIMIT 1)
+             FROM historique
+             WHERE ticket_number >= -1
+             AND id > (SELECT COALESCE(MAX(id), 0) FROM historique WHERE ticket_number = -2)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let date = |d: Option<String>| d.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok());
+
+        let mut reports = Vec::new();
+        if let (Some(first), Some(last)) = (date(first), date(last)) {
+            reports.push((ReportPeriod::Daily, first, last));
+        }
+        if let Some(previous) = date(previous_reset) {
+            if previous.iso_week() != schedule::now().date().iso_week() {
+                let (from, to) = ReportPeriod::Weekly.range(previous);
+                reports.push((ReportPeriod::Weekly, from, to));
+            }
+        }
+        Ok(reports)
+    }
+
+    pub fn build_report(&self, period: ReportPeriod, from: NaiveDate, to: NaiveDate) -> QmsResult<Report> {
+        let query = StatsQuery {
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+            ..StatsQuery::default()
+        };
+        let waiting = self.get_waiting_metrics(&query)?;
+        let stats = self.get_stats_range(&query)?;
+        let heatmap = self.get_heatmap(&query)?;
+        let conn = self.reader()?;
+
+        let mut issued: BTreeMap<Option<String>, u32> = BTreeMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT service, COUNT(*) FROM issued_tickets
+             WHERE date(issued_at, 'localtime') BETWEEN ?1 AND ?2
+             GROUP BY service",
+        )?;
+        let iter = stmt.query_map(params![from.to_string(), to.to_string()], |row| {
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, u32>(1)?))
+        })?;
+        for row in iter {
+            let (service, count) = row?;
+            issued.insert(service, count);
+        }
+
+        let mut open: BTreeMap<String, f64> = BTreeMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT desk_name, SUM(minutes) FROM (
+                 SELECT desk_name,
+                        (julianday(MAX(COALESCE(ended_at, created_at))) - julianday(MIN(created_at))) * 1440 AS minutes
+                 FROM historique
+                 WHERE ticket_number >= -1
+                 AND date(created_at, 'localtime') BETWEEN ?1 AND ?2
+                 GROUP BY desk_name, date(created_at, 'localtime')
+             )
+             GROUP BY desk_name",
+        )?;
+        let iter = stmt.query_map(params![from.to_string(), to.to_string()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<f64>>(1)?))
+        })?;
+        for row in iter {
+            let (desk, minutes) = row?;
+            open.insert(desk, minutes.unwrap_or_default());
+        }
+
+        let mut services: BTreeMap<String, ServiceLine> = BTreeMap::new();
+        for service in waiting.services {
+            let Some(name) = service.service else {
+                continue;
+            };
+            services.insert(
+                name.clone(),
+                ServiceLine {
+                    issued: issued.get(&Some(name.clone())).copied().unwrap_or_default(),
+                    name,
+                    called: service.called,
+                    no_shows: service.no_shows,
+                    avg_wait: service.waiting.avg_minutes,
+                    avg_service: service.serving.avg_minutes,
+                },
+            );
+        }
+        // Issued but never called.
+        for (service, count) in &issued {
+            if let Some(name) = service {
+                services.entry(name.clone()).or_insert_with(|| ServiceLine {
+                    name: name.clone(),
+                    issued: *count,
+                    called: 0,
+                    no_shows: 0,
+                    avg_wait: None,
+                    avg_service: None,
+                });
+            }
+        }
+
+        let desks = stats
+            .desks
+            .into_iter()
+            .map(|desk| DeskLine {
+                uptime_minutes: (open.get(&desk.name).copied().unwrap_or_default() - desk.stats.break_minutes).max(0.0),
+                name: desk.name,
+                calls: desk.stats.served + desk.stats.no_shows,
+                break_minutes: desk.stats.break_minutes,
+                idle_minutes: desk.stats.idle_minutes,
+            })
+            .collect();
+
+        Ok(Report {
+            period,
+            from,
+            to,
+            generated_at: schedule::now().format("%Y-%m-%d %H:%M").to_string(),
+            total: ServiceLine {
+                name: "All services".to_string(),
+                issued: issued.values().sum(),
+                called: waiting.site.called,
+                no_shows: waiting.site.no_shows,
+                avg_wait: waiting.site.waiting.avg_minutes,
+                avg_service: waiting.site.serving.avg_minutes,
+            },
+            services: services.into_values().collect(),
+            calls_by_hour: heatmap.calls_by_hour,
+            desks,
+        })
+    }
+
+    // Builds and saves the report. Blocking.
+    pub fn generate_report(
+        &self,
+        reports: &Reports,
+        period: ReportPeriod,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> QmsResult<ReportFiles> {
+        let report = self.build_report(period, from, to)?;
+        let files = reports.save(&report)?;
+        println!("📊 {} report saved: {}", period.as_str(), files.pdf);
+        Ok(files)
+    }
+}
+
+// The `reports/` folder.
+#[derive(Clone)]
+pub struct Reports {
+    dir: PathBuf,
+}
+
+impl Reports {
+    pub fn open(dir: PathBuf) -> QmsResult<Self> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| QmsError::Internal(format!("Cannot create {}: {}", dir.display(), e)))?;
+        Ok(Reports { dir })
+    }
+
+    fn save(&self, report: &Report) -> QmsResult<ReportFiles> {
+        let stem = report.file_stem();
+        let html = self.dir.join(format!("{}.html", stem));
+        let pdf = self.dir.join(format!("{}.pdf", stem));
+
+        fs::write(&html, render_html(report))
+            .map_err(|e| QmsError::Internal(format!("Cannot write {}: {}", html.display(), e)))?;
+        render_pdf(report, &pdf)?;
+
+        Ok(ReportFiles {
+            html: html.display().to_string(),
+            pdf: pdf.display().to_string(),
+        })
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(report: &Report) -> String {
+    let title = escape(&report.title());
+    let mut html = format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+body {{ font-family: Helvetica, Arial, sans-serif; margin: 2em; color: #222; }}
+h1 {{ font-size: 1.5em; margin-bottom: 0; }}
+h2 {{ font-size: 1.1em; margin-top: 1.5em; border-bottom: 1px solid #ccc; }}
+table {{ border-collapse: collapse; }}
+th, td {{ padding: 4px 12px; text-align: right; border-bottom: 1px solid #eee; }}
+th:first-child, td:first-child {{ text-align: left; }}
+.muted {{ color: #777; }}
+@media print {{ body {{ margin: 0; }} }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class=\"muted\">Generated {}</p>
+",
+        escape(&report.generated_at)
+    );
+
+    for section in report.sections() {
+        html.push_str(&format!("<h2>{}</h2>\n", section.heading));
+        for line in &section.lines {
+            html.push_str(&format!("<p>{}</p>\n", escape(line)));
+        }
+        if let Some((headers, rows)) = &section.table {
+            html.push_str("<table>\n<tr>");
+            for header in headers {
+                html.push_str(&format!("<th>{}</th>", header));
+            }
+            html.push_str("</tr>\n");
+            for row in rows {
+                html.push_str("<tr>");
+                for cell in row {
+                    html.push_str(&format!("<td>{}</td>", escape(cell)));
+                }
+                html.push_str("</tr>\n");
+            }
+            html.push_str("</table>\n");
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const FIRST_COLUMN: f32 = 50.0;
+
+fn pdf_error(e: impl std::fmt::Display) -> QmsError {
+    QmsError::Internal(format!("Cannot write PDF report: {}", e))
+}
+
+// A4 pages filled top to bottom with the built-in Helvetica.
+struct PdfPages<'a> {
+    doc: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    // Distance of the next line from the bottom of the page, in mm.
+    y: f32,
+}
+
+impl PdfPages<'_> {
+    fn advance(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Report");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+        self.y -= height;
+    }
+
+    fn text(&mut self, text: &str, size: f32, bold: bool) {
+        self.advance(size * 0.5);
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer.use_text(text, size, Mm(MARGIN), Mm(self.y), font);
+    }
+
+    fn row(&mut self, cells: &[String], bold: bool) {
+        self.advance(5.0);
+        let font = if bold { &self.bold } else { &self.regular };
+        let others = (cells.len().max(2) - 1) as f32;
+        let width = (PAGE_WIDTH - 2.0 * MARGIN - FIRST_COLUMN) / others;
+        for (i, cell) in cells.iter().enumerate() {
+            let x = match i {
+                0 => MARGIN,
+                _ => MARGIN + FIRST_COLUMN + width * (i - 1) as f32,
+            };
+            let text: String = match i {
+                0 if cell.chars().count() > 28 => cell.chars().take(27).chain(['…']).collect(),
+                _ => cell.clone(),
+            };
+            self.layer.use_text(text, 9.0, Mm(x), Mm(self.y), font);
+        }
+    }
+}
+
+fn render_pdf(report: &Report, path: &Path) -> QmsResult<()> {
+    let title = report.title();
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Report");
+    let mut pages = PdfPages {
+        layer: doc.get_page(page).get_layer(layer),
+        regular: doc.add_builtin_font(BuiltinFont::Helvetica).map_err(pdf_error)?,
+        bold: doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(pdf_error)?,
+        doc: &doc,
+        y: PAGE_HEIGHT - MARGIN,
+    };
+
+    pages.text(&title, 16.0, true);
+    pages.text(&format!("Generated {}", report.generated_at), 9.0, false);
+    for section in report.sections() {
+        pages.advance(4.0);
+        pages.text(section.heading, 12.0, true);
+        for line in &section.lines {
+            pages.text(line, 10.0, false);
+        }
+        if let Some((headers, rows)) = &section.table {
+            let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+            pages.row(&headers, true);
+            for row in rows {
+                pages.row(row, false);
+            }
+        }
+    }
+    drop(pages);
+
+    let file = File::create(path).map_err(|e| QmsError::Internal(format!("Cannot write {}: {}", path.display(), e)))?;
+    doc.save(&mut BufWriter::new(file)).map_err(pdf_error)
+}
@@ -0,0 +1,319 @@
+// When annonces and slides are on screen. `active` stays the admin's on/off switch;
+// the schedule decides, within that, what is shown right now.
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tauri::Emitter;
+use tokio::sync::{broadcast, Notify};
+
+use crate::announcer::{Announcement, Announcer};
+use crate::error::{QmsError, QmsResult};
+use crate::events::ServerEvent;
+use crate::playlist::Slide;
+use crate::{Annonce, Database};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%H:%M";
+const EVERY_DAY: u8 = 0b111_1111;
+
+// When something is shown: date range, daily window and weekdays. Shared by annonces
+// and playlist slides.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct TimeWindow {
+    // "YYYY-MM-DD", both inclusive
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    // "HH:MM" daily window; may cross midnight (22:00 -> 02:00)
+    pub time_start: Option<String>,
+    pub time_end: Option<String>,
+    // ISO weekdays, 1 = Monday ... 7 = Sunday. Empty means every day.
+    pub days: Vec<u8>,
+}
+
+impl TimeWindow {
+    pub fn validate(&self) -> QmsResult<()> {
+        let start = parse_date(&self.start_date)?;
+        let end = parse_date(&self.end_date)?;
+        if let (Some(start), Some(end)) = (start, end) {
+            if end < start {
+                return Err(QmsError::Validation("End date is before start date".to_string()));
+            }
+        }
+
+        let time_start = parse_time(&self.time_start)?;
+        let time_end = parse_time(&self.time_end)?;
+        if time_start.is_some() != time_end.is_some() {
+            return Err(QmsError::Validation("A time window needs both a start and an end".to_string()));
+        }
+
+        if let Some(day) = self.days.iter().find(|d| !(1..=7).contains(*d)) {
+            return Err(QmsError::Validation(format!("Invalid day of week: {} (1-7 expected)", day)));
+        }
+        Ok(())
+    }
+
+    // Values were validated on the way in; anything unreadable is treated as no limit.
+    pub fn is_live(&self, now: NaiveDateTime) -> bool {
+        let today = now.date();
+        if parse_date(&self.start_date).ok().flatten().is_some_and(|start| today < start) {
+            return false;
+        }
+        if parse_date(&self.end_date).ok().flatten().is_some_and(|end| today > end) {
+            return false;
+        }
+
+        let weekday = now.weekday().number_from_monday() as u8;
+        if !self.days.is_empty() && !self.days.contains(&weekday) {
+            return false;
+        }
+
+        let window = (
+            parse_time(&self.time_start).ok().flatten(),
+            parse_time(&self.time_end).ok().flatten(),
+        );
+        match window {
+            (Some(start), Some(end)) if start <= end => (start..end).contains(&now.time()),
+            // Crosses midnight
+            (Some(start), Some(end)) => now.time() >= start || now.time() < end,
+            _ => true,
+        }
+    }
+
+    pub fn days_mask(&self) -> u8 {
+        match self.days.is_empty() {
+            true => EVERY_DAY,
+            false => self.days.iter().fold(0, |mask, day| mask | 1 << (day - 1)),
+        }
+    }
+
+    pub fn days_from_mask(mask: u8) -> Vec<u8> {
+        match mask & EVERY_DAY {
+            EVERY_DAY => Vec::new(),
+            mask => (1..=7).filter(|day| mask & (1 << (day - 1)) != 0).collect(),
+        }
+    }
+
+    // Reads `start_date, end_date, time_start, time_end, days_of_week` from `first` on.
+    pub fn from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Self> {
+        Ok(TimeWindow {
+            start_date: row.get(first)?,
+            end_date: row.get(first + 1)?,
+            time_start: row.get(first + 2)?,
+            time_end: row.get(first + 3)?,
+            days: TimeWindow::days_from_mask(row.get(first + 4)?),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AnnonceSchedule {
+    #[serde(flatten)]
+    pub window: TimeWindow,
+    // How long the display keeps it before moving to the next one.
+    pub display_seconds: u32,
+    // Higher first.
+    pub priority: i32,
+}
+
+impl Default for AnnonceSchedule {
+    fn default() -> Self {
+        AnnonceSchedule {
+            window: TimeWindow::default(),
+            display_seconds: 10,
+            priority: 0,
+        }
+    }
+}
+
+impl AnnonceSchedule {
+    pub fn validate(&self) -> QmsResult<()> {
+        self.window.validate()?;
+        if !(1..=3600).contains(&self.display_seconds) {
+            return Err(QmsError::Validation("Display duration must be between 1 and 3600 seconds".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn is_live(&self, now: NaiveDateTime) -> bool {
+        self.window.is_live(now)
+    }
+}
+
+// Whether and when an annonce is read aloud. It is only ever read while on air.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct AnnonceSpeech {
+    pub audible: bool,
+    // Read when it goes on air, then every N minutes. None reads it once.
+    pub repeat_minutes: Option<u32>,
+    // "HH:MM" times at which it is read too (e.g. 15 minutes before closing).
+    pub speak_times: Vec<String>,
+}
+
+impl AnnonceSpeech {
+    pub fn validate(&self) -> QmsResult<()> {
+        if let Some(minutes) = self.repeat_minutes {
+            if !(1..=1440).contains(&minutes) {
+                return Err(QmsError::Validation("Repeat interval must be between 1 and 1440 minutes".to_string()));
+            }
+        }
+        for time in &self.speak_times {
+            parse_time(&Some(time.clone()))?;
+        }
+        Ok(())
+    }
+
+    // `last` is when it was last read during the current on-air period.
+    fn is_due(&self, now: NaiveDateTime, last: Option<NaiveDateTime>) -> bool {
+        if !self.audible {
+            return false;
+        }
+        let this_minute = now.format(TIME_FORMAT).to_string();
+        let read_this_minute = last.is_some_and(|last| {
+            last.date() == now.date() && last.format(TIME_FORMAT).to_string() == this_minute
+        });
+        if self.speak_times.iter().any(|t| t.trim() == this_minute) && !read_this_minute {
+            return true;
+        }
+        match (last, self.repeat_minutes) {
+            (None, _) => self.speak_times.is_empty() || self.repeat_minutes.is_some(),
+            (Some(last), Some(minutes)) => now - last >= chrono::Duration::minutes(minutes as i64),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn times_column(&self) -> String {
+        self.speak_times.iter().map(|t| t.trim()).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn times_from_column(column: &str) -> Vec<String> {
+        column.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect()
+    }
+}
+
+pub fn parse_date(value: &Option<String>) -> QmsResult<Option<NaiveDate>> {
+    match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => NaiveDate::parse_from_str(v, DATE_FORMAT)
+            .map(Some)
+            .map_err(|_| QmsError::Validation(format!("Invalid date '{}' (YYYY-MM-DD expected)", v))),
+        None => Ok(None),
+    }
+}
+
+fn parse_time(value: &Option<String>) -> QmsResult<Option<NaiveTime>> {
+    match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => NaiveTime::parse_from_str(v, TIME_FORMAT)
+            .map(Some)
+            .map_err(|_| QmsError::Validation(format!("Invalid time '{}' (HH:MM expected)", v))),
+        None => Ok(None),
+    }
+}
+
+pub fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+// Re-evaluates schedules at the start of every minute, and right away when an
+// annonce or slide is edited. Screens are only told when what they show actually changes;
+// audible annonces that are due are handed to the announcer.
+#[derive(Clone)]
+pub struct AnnonceScheduler {
+    changed: Arc<Notify>,
+    tx: broadcast::Sender<ServerEvent>,
+}
+
+impl AnnonceScheduler {
+    pub fn spawn(
+        db: Arc<Database>,
+        app_handle: tauri::AppHandle,
+        tx: broadcast::Sender<ServerEvent>,
+        announcer: Announcer,
+    ) -> Self {
+        let changed = Arc::new(Notify::new());
+        let scheduler = AnnonceScheduler {
+            changed: changed.clone(),
+            tx: tx.clone(),
+        };
+
+        tauri::async_runtime::spawn(async move {
+            let mut on_air: Option<Vec<Annonce>> = None;
+            let mut playing: Option<Vec<Slide>> = None;
+            // Annonce id -> last reading, forgotten once it goes off air.
+            let mut last_spoken: HashMap<i32, NaiveDateTime> = HashMap::new();
+            loop {
+                let annonces = match db.run(|db| db.get_annonces()).await {
+                    Ok(annonces) => annonces,
+                    Err(e) => {
+                        eprintln!("❌ Annonce scheduler: {}", e);
+                        Vec::new()
+                    }
+                };
+                let live: Vec<Annonce> = annonces.iter().filter(|a| a.visible).cloned().collect();
+
+                if on_air.as_ref() != Some(&live) {
+                    println!("📢 {} annonce(s) on air", live.len());
+                    let _ = app_handle.emit("annonces-updated", &annonces);
+                    let _ = tx.send(ServerEvent::Annonces(live.clone()));
+                    on_air = Some(live.clone());
+                }
+
+                let slides = match db.run(|db| db.get_playlist()).await {
+                    Ok(slides) => slides.into_iter().filter(|s| s.is_visible()).collect(),
+                    Err(e) => {
+                        eprintln!("❌ Playlist scheduler: {}", e);
+                        Vec::new()
+                    }
+                };
+                if playing.as_ref() != Some(&slides) {
+                    println!("🖼️ {} slide(s) in the playlist", slides.len());
+                    let _ = tx.send(ServerEvent::Playlist(slides.clone()));
+                    playing = Some(slides);
+                }
+
+                let now = now();
+                last_spoken.retain(|id, _| live.iter().any(|a| a.id == *id));
+                for annonce in &live {
+                    if annonce.speech.is_due(now, last_spoken.get(&annonce.id).copied()) {
+                        println!("📢 Reading annonce {}", annonce.id);
+                        announcer.announce(Announcement::Annonce {
+                            id: annonce.id,
+                            text: annonce.message.clone(),
+                        });
+                        last_spoken.insert(annonce.id, now);
+                    }
+                }
+
+                let seconds = 60 - now.second() as u64;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(seconds)) => {}
+                    _ = changed.notified() => {}
+                }
+            }
+        });
+
+        scheduler
+    }
+
+    // Re-evaluates what is on air now rather than at the next minute.
+    pub fn refresh(&self) {
+        self.changed.notify_one();
+    }
+
+    // Tells the screens about an edit, then re-evaluates what is on air. Screens only
+    // get live annonces: one that is off air after the edit is removed from them.
+    pub fn publish(&self, event: ServerEvent) {
+        let event = match event {
+            ServerEvent::AnnonceAdded(annonce) if !annonce.visible => None,
+            ServerEvent::AnnonceUpdated(annonce) | ServerEvent::AnnonceToggled(annonce) if !annonce.visible => {
+                Some(ServerEvent::AnnonceDeleted { id: annonce.id })
+            }
+            event => Some(event),
+        };
+        if let Some(event) = event {
+            let _ = self.tx.send(event);
+        }
+        self.changed.notify_one();
+    }
+}
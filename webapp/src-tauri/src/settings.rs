@@ -0,0 +1,61 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::audit::{self, Audit};
+use crate::error::{QmsError, QmsResult};
+use crate::Database;
+
+// Keys of the `settings` table.
+pub const TTS: &str = "tts";
+pub const SOUNDS: &str = "sounds";
+pub const SLA: &str = "sla";
+
+impl Database {
+    pub fn get_setting<T: DeserializeOwned + Default>(&self, key: &str) -> QmsResult<T> {
+        let conn = self.reader()?;
+        read_setting(&conn, key)
+    }
+
+    // The audit entry gets the value replaced and the new one.
+    pub fn set_setting<T: Serialize + DeserializeOwned + Default>(
+        &self,
+        key: &str,
+        value: &T,
+        audit: &Audit,
+    ) -> QmsResult<()> {
+        let raw = serde_json::to_string(value)
+            .map_err(|e| QmsError::Validation(format!("Invalid setting '{}': {}", key, e)))?;
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before: T = read_setting(&tx, key)?;
+        tx.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, raw],
+        )?;
+        audit.record(&tx, audit::value(&before), audit::value(value))?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// Missing keys fall back to the type's default; a value that no longer parses
+// (e.g. written by an older build) is reported and replaced by the default too.
+fn read_setting<T: DeserializeOwned + Default>(conn: &Connection, key: &str) -> QmsResult<T> {
+    let raw: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(match raw {
+        Some(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+            eprintln!("⚠️ Invalid setting '{}', using default: {}", key, e);
+            T::default()
+        }),
+        None => T::default(),
+    })
+}
@@ -0,0 +1,319 @@
+// Spells ticket numbers and desk numbers out in words so that every language is read
+// correctly, even with a voice made for another language (there are few Lingala voices,
+// so Lingala is usually spoken by a French one).
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lang {
+    French,
+    English,
+    Lingala,
+}
+
+impl Lang {
+    fn from_tag(tag: &str) -> Option<Lang> {
+        let primary = tag.split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+        match primary.as_str() {
+            "fr" => Some(Lang::French),
+            "en" => Some(Lang::English),
+            "ln" => Some(Lang::Lingala),
+            _ => None,
+        }
+    }
+
+    fn zero(self) -> &'static str {
+        match self {
+            Lang::French => "zéro",
+            Lang::English => "zero",
+            Lang::Lingala => "zero",
+        }
+    }
+}
+
+// "A012" -> "A zéro douze", "12" -> "douze". Letters are kept and separated so engines
+// read them as letters; leading zeros are read one by one, the rest as a number.
+// Unknown languages get the ticket unchanged and let the engine decide.
+pub fn spell_ticket(ticket: &str, language: &str) -> String {
+    let Some(lang) = Lang::from_tag(language) else {
+        return ticket.to_string();
+    };
+
+    let mut words = Vec::new();
+    let mut digits = String::new();
+    for c in ticket.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else {
+            flush_digits(&mut digits, lang, &mut words, true);
+            if c.is_alphanumeric() {
+                words.push(c.to_uppercase().to_string());
+            }
+        }
+    }
+    flush_digits(&mut digits, lang, &mut words, true);
+
+    words.join(" ")
+}
+
+// Replaces every run of digits inside free text ("Guichet 3") by its spelling.
+pub fn spell_numbers_in(text: &str, language: &str) -> String {
+    let Some(lang) = Lang::from_tag(language) else {
+        return text.to_string();
+    };
+
+    let mut out = String::new();
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if !digits.is_empty() {
+            let mut words = Vec::new();
+            flush_digits(&mut digits, lang, &mut words, false);
+            out.push_str(&words.join(" "));
+            // "10b" -> "ten b", not "tenb"
+            if c.is_alphanumeric() {
+                out.push(' ');
+            }
+        }
+        out.push(c);
+    }
+    if !digits.is_empty() {
+        let mut words = Vec::new();
+        flush_digits(&mut digits, lang, &mut words, false);
+        out.push_str(&words.join(" "));
+    }
+    out
+}
+
+fn flush_digits(digits: &mut String, lang: Lang, words: &mut Vec<String>, read_leading_zeros: bool) {
+    if digits.is_empty() {
+        return;
+    }
+
+    let significant = digits.trim_start_matches('0');
+    if read_leading_zeros {
+        let zeros = digits.len() - significant.len();
+        // "000" is read "zéro zéro zéro", "012" as "zéro douze".
+        for _ in 0..zeros {
+            words.push(lang.zero().to_string());
+        }
+    } else if significant.is_empty() {
+        words.push(lang.zero().to_string());
+    }
+
+    if !significant.is_empty() {
+        // Past a million, reading digit by digit is clearer than a huge number.
+        match significant.parse::<u32>() {
+            Ok(n) if n < 1_000_000 => words.push(number(n, lang)),
+            _ => {
+                for d in significant.chars() {
+                    let n = d.to_digit(10).unwrap_or(0);
+                    words.push(number(n, lang));
+                }
+            }
+        }
+    }
+    digits.clear();
+}
+
+fn number(n: u32, lang: Lang) -> String {
+    match lang {
+        Lang::French => french(n),
+        Lang::English => english(n),
+        Lang::Lingala => lingala(n),
+    }
+}
+
+const FR_UNITS: [&str; 17] = [
+    "zéro", "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf", "dix",
+    "onze", "douze", "treize", "quatorze", "quinze", "seize",
+];
+
+fn french(n: u32) -> String {
+    match n {
+        0..=16 => FR_UNITS[n as usize].to_string(),
+        17..=19 => format!("dix-{}", FR_UNITS[(n - 10) as usize]),
+        20..=69 => {
+            let tens = ["", "", "vingt", "trente", "quarante", "cinquante", "soixante"][(n / 10) as usize];
+            match n % 10 {
+                0 => tens.to_string(),
+                1 => format!("{} et un", tens),
+                u => format!("{}-{}", tens, FR_UNITS[u as usize]),
+            }
+        }
+        // 70-79 and 90-99 are built on 60 and 80: soixante-dix, quatre-vingt-onze
+        70..=79 => match n {
+            71 => "soixante et onze".to_string(),
+            _ => format!("soixante-{}", french(n - 60)),
+        },
+        80 => "quatre-vingts".to_string(),
+        81..=99 => format!("quatre-vingt-{}", french(n - 80)),
+        100..=999 => {
+            let (hundreds, rest) = (n / 100, n % 100);
+            let head = match (hundreds, rest) {
+                (1, _) => "cent".to_string(),
+                (h, 0) => format!("{} cents", FR_UNITS[h as usize]),
+                (h, _) => format!("{} cent", FR_UNITS[h as usize]),
+            };
+            if rest == 0 {
+                head
+            } else {
+                format!("{} {}", head, french(rest))
+            }
+        }
+        _ => {
+            let (thousands, rest) = (n / 1000, n % 1000);
+            let head = match thousands {
+                1 => "mille".to_string(),
+                t => {
+                    let count = french(t);
+                    // "deux cents" and "quatre-vingts" lose their s before "mille", "trois" keeps it
+                    let count = match count.strip_suffix('s') {
+                        Some(stem) if stem.ends_with("cent") || stem.ends_with("vingt") => stem,
+                        _ => &count,
+                    };
+                    format!("{} mille", count)
+                }
+            };
+            if rest == 0 {
+                head
+            } else {
+                format!("{} {}", head, french(rest))
+            }
+        }
+    }
+}
+
+const EN_UNITS: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen",
+    "nineteen",
+];
+
+fn english(n: u32) -> String {
+    match n {
+        0..=19 => EN_UNITS[n as usize].to_string(),
+        20..=99 => {
+            let tens = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"]
+                [(n / 10) as usize];
+            match n % 10 {
+                0 => tens.to_string(),
+                u => format!("{}-{}", tens, EN_UNITS[u as usize]),
+            }
+        }
+        100..=999 => {
+            let head = format!("{} hundred", EN_UNITS[(n / 100) as usize]);
+            match n % 100 {
+                0 => head,
+                rest => format!("{} {}", head, english(rest)),
+            }
+        }
+        _ => {
+            let head = format!("{} thousand", english(n / 1000));
+            match n % 1000 {
+                0 => head,
+                rest => format!("{} {}", head, english(rest)),
+            }
+        }
+    }
+}
+
+const LN_UNITS: [&str; 10] = [
+    "zero", "moko", "mibale", "misato", "minei", "mitano", "motoba", "nsambo", "mwambe", "libwa",
+];
+
+// Lingala counts additively with "na": 23 = ntuku mibale na misato.
+fn lingala(n: u32) -> String {
+    let mut parts = Vec::new();
+
+    let thousands = n / 1000;
+    if thousands > 0 {
+        parts.push(format!("nkoto {}", lingala(thousands)));
+    }
+    let hundreds = (n % 1000) / 100;
+    if hundreds > 0 {
+        parts.push(format!("nkama {}", LN_UNITS[hundreds as usize]));
+    }
+    let tens = (n % 100) / 10;
+    match tens {
+        0 => {}
+        1 => parts.push("zomi".to_string()),
+        t => parts.push(format!("ntuku {}", LN_UNITS[t as usize])),
+    }
+    let units = n % 10;
+    if units > 0 || parts.is_empty() {
+        parts.push(LN_UNITS[units as usize].to_string());
+    }
+
+    parts.join(" na ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn french_numbers() {
+        let cases = [
+            (0, "zéro"),
+            (21, "vingt et un"),
+            (71, "soixante et onze"),
+            (80, "quatre-vingts"),
+            (99, "quatre-vingt-dix-neuf"),
+            (200, "deux cents"),
+            (3000, "trois mille"),
+            (1999, "mille neuf cent quatre-vingt-dix-neuf"),
+            (80_000, "quatre-vingt mille"),
+            (200_000, "deux cent mille"),
+        ];
+        for (n, words) in cases {
+            assert_eq!(french(n), words, "{}", n);
+        }
+    }
+
+    #[test]
+    fn english_numbers() {
+        let cases = [
+            (0, "zero"),
+            (21, "twenty-one"),
+            (71, "seventy-one"),
+            (80, "eighty"),
+            (99, "ninety-nine"),
+            (200, "two hundred"),
+            (3000, "three thousand"),
+            (1999, "one thousand nine hundred ninety-nine"),
+        ];
+        for (n, words) in cases {
+            assert_eq!(english(n), words, "{}", n);
+        }
+    }
+
+    #[test]
+    fn lingala_numbers() {
+        let cases = [
+            (0, "zero"),
+            (21, "ntuku mibale na moko"),
+            (71, "ntuku nsambo na moko"),
+            (80, "ntuku mwambe"),
+            (99, "ntuku libwa na libwa"),
+            (200, "nkama mibale"),
+            (3000, "nkoto misato"),
+            (1999, "nkoto moko na nkama libwa na ntuku libwa na libwa"),
+        ];
+        for (n, words) in cases {
+            assert_eq!(lingala(n), words, "{}", n);
+        }
+    }
+
+    #[test]
+    fn tickets() {
+        assert_eq!(spell_ticket("A012", "fr-FR"), "A zéro douze");
+        assert_eq!(spell_ticket("000", "fr"), "zéro zéro zéro");
+        assert_eq!(spell_ticket("3000", "fr"), "trois mille");
+        assert_eq!(spell_ticket("b71", "en-US"), "B seventy-one");
+        assert_eq!(spell_ticket("1999", "ln"), "nkoto moko na nkama libwa na ntuku libwa na libwa");
+        // Unknown language: left to the engine.
+        assert_eq!(spell_ticket("A012", "de-DE"), "A012");
+    }
+}
@@ -0,0 +1,300 @@
+// Staff accounts and who is working at which desk. An operator logs in at a desk
+// from the admin app or with their PIN on the desk's device; every call made at the
+// desk until they log out is attributed to them in `historique.operator_id`.
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::audit::{self, Audit};
+use crate::auth::{self, Role};
+use crate::error::{QmsError, QmsResult};
+use crate::Database;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Staff {
+    id: i32,
+    name: String,
+    role: Role,
+    active: bool,
+    has_pin: bool,
+    // Needed to log in to the admin app; the PIN works there too.
+    has_password: bool,
+}
+
+impl Staff {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Staff {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            active: row.get(2)?,
+            has_pin: row.get::<_, Option<String>>(3)?.is_some(),
+            role: Role::from_column(&row.get::<_, String>(4)?),
+            has_password: row.get::<_, Option<String>>(5)?.is_some(),
+        })
+    }
+}
+
+const STAFF_COLUMNS: &str = "id, name, active, pin_hash, role, password_hash";
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DeskSession {
+    id: i32,
+    desk_name: String,
+    staff_id: i32,
+    staff_name: String,
+    // Local time, "YYYY-MM-DD HH:MM:SS"
+    started_at: String,
+    ended_at: Option<String>,
+}
+
+// 4 to 8 digits, typed on the desk's keypad.
+fn validate_pin(pin: &str) -> QmsResult<()> {
+    if !(4..=8).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(QmsError::Validation("PIN must be 4 to 8 digits".to_string()));
+    }
+    Ok(())
+}
+
+pub fn hash_secret(secret: &str) -> QmsResult<String> {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| QmsError::Internal(format!("Cannot hash secret: {}", e)))
+}
+
+pub fn verify_secret(secret: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| Argon2::default().verify_password(secret.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
+
+fn validate_name(name: &str) -> QmsResult<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(QmsError::Validation("Staff name cannot be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn hash_pin(pin: Option<String>) -> QmsResult<Option<String>> {
+    match pin {
+        Some(pin) => {
+            validate_pin(&pin)?;
+            Some(hash_secret(&pin)).transpose()
+        }
+        None => Ok(None),
+    }
+}
+
+fn hash_password(password: Option<String>) -> QmsResult<Option<String>> {
+    match password {
+        Some(password) => {
+            auth::validate_password(&password)?;
+            Some(hash_secret(&password)).transpose()
+        }
+        None => Ok(None),
+    }
+}
+
+impl Database {
+    pub fn get_staff(&self) -> QmsResult<Vec<Staff>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM staff ORDER BY name", STAFF_COLUMNS))?;
+        let iter = stmt.query_map([], Staff::from_row)?;
+        let staff = iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(staff)
+    }
+
+    pub fn add_staff(
+        &self,
+        name: String,
+        role: Role,
+        pin: Option<String>,
+        password: Option<String>,
+        audit: &Audit,
+    ) -> QmsResult<Staff> {
+        let name = validate_name(&name)?;
+        let pin_hash = hash_pin(pin)?;
+        let password_hash = hash_password(password)?;
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO staff (name, role, pin_hash, password_hash) VALUES (?1, ?2, ?3, ?4)",
+            params![name, role.as_str(), pin_hash, password_hash],
+        )?;
+        let staff = staff_by_id(&tx, tx.last_insert_rowid() as i32)?;
+        audit.record(&tx, None, audit::value(&staff))?;
+        tx.commit()?;
+        Ok(staff)
+    }
+
+    // The PIN and password are left as they are when None. Staff are deactivated
+    // rather than deleted so their past calls stay attributed; deactivating logs
+    // them out.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_staff(
+        &self,
+        id: i32,
+        name: String,
+        role: Role,
+        active: bool,
+        pin: Option<String>,
+        password: Option<String>,
+        audit: &Audit,
+    ) -> QmsResult<Staff> {
+        let name = validate_name(&name)?;
+        let pin_hash = hash_pin(pin)?;
+        let password_hash = hash_password(password)?;
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let before = staff_by_id(&tx, id)?;
+        tx.execute(
+            "UPDATE staff SET name = ?1, role = ?2, active = ?3,
+                              pin_hash = COALESCE(?4, pin_hash), password_hash = COALESCE(?5, password_hash)
+             WHERE id = ?6",
+            params![name, role.as_str(), active, pin_hash, password_hash, id],
+        )?;
+        // Nobody could manage accounts any more.
+        let admins: u32 = tx.query_row(
+            "SELECT COUNT(*) FROM staff WHERE role = 'admin' AND active = 1",
+            [],
+            |row| row.get(0),
+        )?;
+        if admins == 0 {
+            return Err(QmsError::Validation("At least one active admin is required".to_string()));
+        }
+        if !active {
+            tx.execute(
+                "UPDATE desk_sessions SET ended_at = CURRENT_TIMESTAMP WHERE staff_id = ?1 AND ended_at IS NULL",
+                params![id],
+            )?;
+        }
+        let staff = staff_by_id(&tx, id)?;
+        audit.record(&tx, audit::value(&before), audit::value(&staff))?;
+        tx.commit()?;
+        Ok(staff)
+    }
+
+    // From the admin app: no PIN needed.
+    pub fn login_operator(&self, desk_name: &str, staff_id: i32, audit: &Audit) -> QmsResult<DeskSession> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+
+        let active: Option<bool> = tx
+            .query_row("SELECT active FROM staff WHERE id = ?1", params![staff_id], |row| row.get(0))
+            .optional()?;
+        match active {
+            None => return Err(QmsError::NotFound(format!("Staff {} not found", staff_id))),
+            Some(false) => return Err(QmsError::Validation("This staff account is deactivated".to_string())),
+            Some(true) => {}
+        }
+        let desk_exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM devices WHERE name = ?1)",
+            params![desk_name],
+            |row| row.get(0),
+        )?;
+        if !desk_exists {
+            return Err(QmsError::NotFound(format!("Desk {} not found", desk_name)));
+        }
+
+        // One operator per desk, one desk per operator.
+        tx.execute(
+            "UPDATE desk_sessions SET ended_at = CURRENT_TIMESTAMP
+             WHERE ended_at IS NULL AND (desk_name = ?1 OR staff_id = ?2)",
+            params![desk_name, staff_id],
+        )?;
+        tx.execute(
+            "INSERT INTO desk_sessions (desk_name, staff_id) VALUES (?1, ?2)",
+            params![desk_name, staff_id],
+        )?;
+        let session = session_by_id(&tx, tx.last_insert_rowid() as i32)?;
+        audit.record(&tx, None, audit::value(&session))?;
+        tx.commit()?;
+        Ok(session)
+    }
+
+    // From the desk's device. Failures do not say whether the account or the PIN
+    // was wrong.
+    pub fn login_with_pin(&self, desk_name: &str, staff_id: i32, pin: &str, audit: &Audit) -> QmsResult<DeskSession> {
+        let hash: Option<String> = self
+            .reader()?
+            .query_row(
+                "SELECT pin_hash FROM staff WHERE id = ?1 AND active = 1",
+                params![staff_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        if !hash.is_some_and(|hash| verify_secret(pin, &hash)) {
+            return Err(QmsError::Unauthorized("Invalid staff or PIN".to_string()));
+        }
+        self.login_operator(desk_name, staff_id, audit)
+    }
+
+    pub fn logout_operator(&self, desk_name: &str, audit: &Audit) -> QmsResult<Option<DeskSession>> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let id: Option<i32> = tx
+            .query_row(
+                "UPDATE desk_sessions SET ended_at = CURRENT_TIMESTAMP
+                 WHERE desk_name = ?1 AND ended_at IS NULL
+                 RETURNING id",
+                params![desk_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let session = id.map(|id| session_by_id(&tx, id)).transpose()?;
+        if let Some(session) = &session {
+            audit.record(&tx, audit::value(session), None)?;
+        }
+        tx.commit()?;
+        Ok(session)
+    }
+
+    // Who is logged in where right now.
+    pub fn get_desk_sessions(&self) -> QmsResult<Vec<DeskSession>> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE s.ended_at IS NULL ORDER BY s.desk_name",
+            SESSION_SELECT
+        ))?;
+        let iter = stmt.query_map([], session_from_row)?;
+        let sessions = iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(sessions)
+    }
+}
+
+const SESSION_SELECT: &str = "SELECT s.id, s.desk_name, s.staff_id, staff.name,
+                                     datetime(s.started_at, 'localtime'), datetime(s.ended_at, 'localtime')
+                              FROM desk_sessions s JOIN staff ON staff.id = s.staff_id";
+
+fn session_from_row(row: &rusqlite::Row) -> rusqlite::Result<DeskSession> {
+    Ok(DeskSession {
+        id: row.get(0)?,
+        desk_name: row.get(1)?,
+        staff_id: row.get(2)?,
+        staff_name: row.get(3)?,
+        started_at: row.get(4)?,
+        ended_at: row.get(5)?,
+    })
+}
+
+fn session_by_id(conn: &Connection, id: i32) -> QmsResult<DeskSession> {
+    conn.query_row(&format!("{} WHERE s.id = ?1", SESSION_SELECT), params![id], session_from_row)
+        .optional()?
+        .ok_or_else(|| QmsError::NotFound(format!("Desk session {} not found", id)))
+}
+
+fn staff_by_id(conn: &Connection, id: i32) -> QmsResult<Staff> {
+    conn.query_row(
+        &format!("SELECT {} FROM staff WHERE id = ?1", STAFF_COLUMNS),
+        params![id],
+        Staff::from_row,
+    )
+    .optional()?
+    .ok_or_else(|| QmsError::NotFound(format!("Staff {} not found", id)))
+}
@@ -0,0 +1,414 @@
+// Statistics over any date range, read straight from `historique`.
+//
+// Each desk's rows are replayed in order. A ticket is served from its call until
+// `ended_at` (the desk pressed "done") or else the desk's next row; the time from
+// `ended_at` to the next call is idle time. A `-1` row starts a break that lasts until
+// the desk's next call. A `-2` reset ends everything in progress. Nothing is carried
+// over midnight: a ticket or break still open at the end of its day has no duration.
+// A no-show is counted on its own: nobody was served, so it has no service time, but
+// the time after it is idle as usual.
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::error::{QmsError, QmsResult};
+use crate::export::{ExportFormat, ExportWriter};
+use crate::history::{HistoryQuery, TicketStatus};
+use crate::schedule;
+use crate::Database;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct StatsQuery {
+    // "YYYY-MM-DD", both inclusive, local dates. Default to today.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // Only this desk / service / operator in every breakdown.
+    pub desk: Option<String>,
+    pub service: Option<String>,
+    pub operator_id: Option<i32>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct StatsSummary {
+    pub served: u32,
+    pub no_shows: u32,
+    // Minutes, over the tickets whose end is known.
+    avg_service_minutes: Option<f64>,
+    median_service_minutes: Option<f64>,
+    p90_service_minutes: Option<f64>,
+    pub idle_minutes: f64,
+    breaks: u32,
+    pub break_minutes: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GroupStats {
+    pub name: String,
+    #[serde(flatten)]
+    pub stats: StatsSummary,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StatsRange {
+    from: String,
+    to: String,
+    site: StatsSummary,
+    pub desks: Vec<GroupStats>,
+    services: Vec<GroupStats>,
+    // Calls made while nobody was logged in are only in the other breakdowns.
+    operators: Vec<GroupStats>,
+}
+
+// One line of an export: the site, or one desk / service / operator.
+#[derive(Serialize, Debug)]
+struct StatsRecord {
+    from: String,
+    to: String,
+    // "site", "desk", "service" or "operator".
+    scope: &'static str,
+    name: Option<String>,
+    #[serde(flatten)]
+    stats: StatsSummary,
+}
+
+const STATS_COLUMNS: &[&str] = &[
+    "from",
+    "to",
+    "scope",
+    "name",
+    "served",
+    "no_shows",
+    "avg_service_minutes",
+    "median_service_minutes",
+    "p90_service_minutes",
+    "idle_minutes",
+    "breaks",
+    "break_minutes",
+];
+
+// Raw figures, summarised once everything is replayed.
+#[derive(Default)]
+struct Tally {
+    served: u32,
+    no_shows: u32,
+    service_minutes: Vec<f64>,
+    idle_minutes: f64,
+    breaks: u32,
+    break_minutes: f64,
+}
+
+impl Tally {
+    fn summary(mut self) -> StatsSummary {
+        self.service_minutes.sort_by(|a, b| a.total_cmp(b));
+        let times = &self.service_minutes;
+        StatsSummary {
+            served: self.served,
+            no_shows: self.no_shows,
+            avg_service_minutes: (!times.is_empty()).then(|| round(times.iter().sum::<f64>() / times.len() as f64)),
+            median_service_minutes: median(times).map(round),
+            p90_service_minutes: percentile(times, 90.0).map(round),
+            idle_minutes: round(self.idle_minutes),
+            breaks: self.breaks,
+            break_minutes: round(self.break_minutes),
+        }
+    }
+}
+
+// Who a ticket or break is counted against, besides its desk.
+#[derive(Clone, Default)]
+struct Owner {
+    service: Option<String>,
+    operator: Option<String>,
+}
+
+// The breakdowns are fed the same figures.
+#[derive(Default)]
+struct Tallies {
+    site: Tally,
+    desks: BTreeMap<String, Tally>,
+    services: BTreeMap<String, Tally>,
+    operators: BTreeMap<String, Tally>,
+}
+
+impl Tallies {
+    fn each(&mut self, desk: &str, owner: &Owner, f: impl Fn(&mut Tally)) {
+        f(&mut self.site);
+        f(self.desks.entry(desk.to_string()).or_default());
+        if let Some(service) = &owner.service {
+            f(self.services.entry(service.clone()).or_default());
+        }
+        if let Some(operator) = &owner.operator {
+            f(self.operators.entry(operator.clone()).or_default());
+        }
+    }
+}
+
+struct Row {
+    ticket_number: i32,
+    desk_name: String,
+    service: Option<String>,
+    operator_id: Option<i32>,
+    operator_name: Option<String>,
+    status: String,
+    created_at: NaiveDateTime,
+    ended_at: Option<NaiveDateTime>,
+}
+
+// What a desk is doing between two of its rows.
+enum Open {
+    Ticket {
+        called_at: NaiveDateTime,
+        ended_at: Option<NaiveDateTime>,
+        no_show: bool,
+        owner: Owner,
+    },
+    Break {
+        started_at: NaiveDateTime,
+        owner: Owner,
+    },
+}
+
+impl Open {
+    // Closes the ticket or break at `at`, or at the end of its own data when `at` is
+    // None or on another day.
+    fn close(self, desk: &str, at: Option<NaiveDateTime>, tallies: &mut Tallies) {
+        match self {
+            Open::Ticket { called_at, ended_at, no_show, owner } => {
+                let next = at.filter(|at| at.date() == called_at.date());
+                let Some(end) = ended_at.or(next) else {
+                    return;
+                };
+                let minutes = minutes_between(called_at, end);
+                let idle = match (ended_at, next) {
+                    (Some(ended_at), Some(next)) => minutes_between(ended_at, next),
+                    _ => 0.0,
+                };
+                tallies.each(desk, &owner, |t| {
+                    if !no_show {
+                        t.service_minutes.push(minutes);
+                    }
+                    t.idle_minutes += idle;
+                });
+            }
+            Open::Break { started_at, owner } => {
+                if let Some(end) = at.filter(|at| at.date() == started_at.date()) {
+                    let minutes = minutes_between(started_at, end);
+                    tallies.each(desk, &owner, |t| t.break_minutes += minutes);
+                }
+            }
+        }
+    }
+}
+
+// Exports take the history's filters. Status, search and paging have no meaning
+// for totals and are ignored; without dates, the range is today as usual.
+impl From<&HistoryQuery> for StatsQuery {
+    fn from(query: &HistoryQuery) -> Self {
+        StatsQuery {
+            from: query.from.clone(),
+            to: query.to.clone(),
+            desk: query.desk.clone(),
+            service: query.service.clone(),
+            operator_id: query.operator_id,
+        }
+    }
+}
+
+impl StatsQuery {
+    pub fn range(&self) -> QmsResult<(NaiveDate, NaiveDate)> {
+        let today = schedule::now().date();
+        let from = schedule::parse_date(&self.from)?.unwrap_or(today);
+        let to = schedule::parse_date(&self.to)?.unwrap_or(today.max(from));
+        if to < from {
+            return Err(QmsError::Validation("End date is before start date".to_string()));
+        }
+        Ok((from, to))
+    }
+}
+
+impl Database {
+    pub fn get_stats_range(&self, query: &StatsQuery) -> QmsResult<StatsRange> {
+        let (from, to) = query.range()?;
+
+        let desk = query.desk.as_deref().map(str::trim).filter(|d| !d.is_empty());
+        let service = query.service.as_deref().map(str::trim).filter(|s| !s.is_empty());
+
+        let mut tallies = Tallies::default();
+        let mut open: HashMap<String, Open> = HashMap::new();
+        // Last service seen per desk; breaks are counted against it.
+        let mut desk_service: HashMap<String, Option<String>> = HashMap::new();
+
+        self.for_each_stats_row(from, to, |row| {
+            if row.ticket_number == -2 {
+                for (desk_name, state) in open.drain() {
+                    state.close(&desk_name, Some(row.created_at), &mut tallies);
+                }
+                return;
+            }
+            if desk.is_some_and(|d| d != row.desk_name) {
+                return;
+            }
+            // Whatever the desk was doing ends here, even if this row is filtered out.
+            if let Some(state) = open.remove(&row.desk_name) {
+                state.close(&row.desk_name, Some(row.created_at), &mut tallies);
+            }
+            let row_service = match row.ticket_number {
+                -1 => desk_service.get(&row.desk_name).cloned().flatten(),
+                _ => row.service.clone(),
+            };
+            if row.ticket_number >= 0 {
+                desk_service.insert(row.desk_name.clone(), row_service.clone());
+            }
+            if service.is_some_and(|s| row_service.as_deref() != Some(s)) {
+                return;
+            }
+            if query.operator_id.is_some_and(|id| row.operator_id != Some(id)) {
+                return;
+            }
+
+            let owner = Owner {
+                service: row_service,
+                operator: row.operator_name,
+            };
+            let state = if row.ticket_number == -1 {
+                tallies.each(&row.desk_name, &owner, |t| t.breaks += 1);
+                Open::Break {
+                    started_at: row.created_at,
+                    owner,
+                }
+            } else {
+                let no_show = row.status == TicketStatus::NoShow.as_str();
+                tallies.each(&row.desk_name, &owner, |t| {
+                    if no_show {
+                        t.no_shows += 1;
+                    } else {
+                        t.served += 1;
+                    }
+                });
+                Open::Ticket {
+                    called_at: row.created_at,
+                    ended_at: row.ended_at,
+                    no_show,
+                    owner,
+                }
+            };
+            open.insert(row.desk_name, state);
+        })?;
+        for (desk_name, state) in open.drain() {
+            state.close(&desk_name, None, &mut tallies);
+        }
+
+        let group = |tallies: BTreeMap<String, Tally>| {
+            tallies
+                .into_iter()
+                .map(|(name, tally)| GroupStats { name, stats: tally.summary() })
+                .collect()
+        };
+        Ok(StatsRange {
+            from: from.to_string(),
+            to: to.to_string(),
+            site: tallies.site.summary(),
+            desks: group(tallies.desks),
+            services: group(tallies.services),
+            operators: group(tallies.operators),
+        })
+    }
+
+    // The site first, then every desk, service and operator. Returns the number of
+    // lines written.
+    pub fn export_stats(&self, query: &StatsQuery, path: &Path, format: ExportFormat) -> QmsResult<u64> {
+        let range = self.get_stats_range(query)?;
+        let mut writer = ExportWriter::create(path, format, "Statistics", STATS_COLUMNS)?;
+        let record = |scope, name, stats| StatsRecord {
+            from: range.from.clone(),
+            to: range.to.clone(),
+            scope,
+            name,
+            stats,
+        };
+
+        writer.write(&record("site", None, range.site.clone()))?;
+        let groups = [("desk", &range.desks), ("service", &range.services), ("operator", &range.operators)];
+        for (scope, groups) in groups {
+            for group in groups {
+                writer.write(&record(scope, Some(group.name.clone()), group.stats.clone()))?;
+            }
+        }
+        writer.finish()
+    }
+
+    // Tickets, breaks and resets of the range, in order, with local timestamps. Fed
+    // one at a time: long ranges are never held in memory.
+    fn for_each_stats_row(&self, from: NaiveDate, to: NaiveDate, mut f: impl FnMut(Row)) -> QmsResult<()> {
+        let conn = self.reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT ticket_number, desk_name, service, operator_id,
+                    (SELECT name FROM staff WHERE staff.id = operator_id), status,
+                    datetime(created_at, 'localtime'), datetime(ended_at, 'localtime')
+             FROM historique
+             WHERE date(created_at, 'localtime') BETWEEN ?1 AND ?2
+             ORDER BY id",
+        )?;
+        let iter = stmt.query_map(rusqlite::params![from.to_string(), to.to_string()], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<i32>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })?;
+
+        for raw in iter {
+            let (ticket_number, desk_name, service, operator_id, operator_name, status, created_at, ended_at) = raw?;
+            // Rows with an unreadable timestamp cannot be placed in time.
+            let Ok(created_at) = NaiveDateTime::parse_from_str(&created_at, TIMESTAMP_FORMAT) else {
+                continue;
+            };
+            f(Row {
+                ticket_number,
+                desk_name,
+                service,
+                operator_id,
+                operator_name,
+                status,
+                created_at,
+                ended_at: ended_at.and_then(|e| NaiveDateTime::parse_from_str(&e, TIMESTAMP_FORMAT).ok()),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn minutes_between(start: NaiveDateTime, end: NaiveDateTime) -> f64 {
+    ((end - start).num_seconds().max(0) as f64) / 60.0
+}
+
+fn round(minutes: f64) -> f64 {
+    (minutes * 100.0).round() / 100.0
+}
+
+// `sorted` must be in ascending order.
+pub fn median(sorted: &[f64]) -> Option<f64> {
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[n / 2]),
+        n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0),
+    }
+}
+
+// Nearest-rank percentile; `sorted` must be in ascending order.
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
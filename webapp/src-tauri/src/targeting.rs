@@ -0,0 +1,74 @@
+// Which screens an annonce is for. A screen gets it when it matches any of the
+// lists: its device name, its group, or its service. No targets at all means every
+// screen, as before targeting existed.
+use serde::{Deserialize, Serialize};
+
+use crate::error::{QmsError, QmsResult};
+use crate::events::ServerEvent;
+use crate::Device;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct AnnonceTargets {
+    // Device names, so a screen registered again under the same name keeps its annonces.
+    pub devices: Vec<String>,
+    pub groups: Vec<String>,
+    pub services: Vec<String>,
+}
+
+impl AnnonceTargets {
+    pub fn validate(&self) -> QmsResult<()> {
+        let mut all = self.devices.iter().chain(&self.groups).chain(&self.services);
+        if all.any(|t| t.trim().is_empty()) {
+            return Err(QmsError::Validation("Announcement targets cannot be blank".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn is_everyone(&self) -> bool {
+        self.devices.is_empty() && self.groups.is_empty() && self.services.is_empty()
+    }
+
+    // Names are compared the way they are typed in the admin: trimmed, any case.
+    pub fn matches(&self, device: &Device) -> bool {
+        fn listed(list: &[String], value: Option<&str>) -> bool {
+            value.is_some_and(|v| list.iter().any(|t| t.trim().eq_ignore_ascii_case(v.trim())))
+        }
+
+        self.is_everyone()
+            || listed(&self.devices, Some(&device.name))
+            || listed(&self.groups, device.group.as_deref())
+            || listed(&self.services, device.service.as_deref())
+    }
+
+    // Stored as JSON in `annonces.targets`.
+    pub fn to_column(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    // Anything unreadable is treated as no targeting.
+    pub fn from_column(column: &str) -> Self {
+        serde_json::from_str(column).unwrap_or_default()
+    }
+}
+
+// What one screen gets out of a broadcast event. Annonces for other screens are
+// dropped; an edit that takes one away from this screen becomes a deletion so the
+// screen stops showing it.
+pub fn for_device(event: ServerEvent, device: &Device) -> Option<ServerEvent> {
+    match event {
+        ServerEvent::AnnonceAdded(annonce) => {
+            annonce.targets.matches(device).then_some(ServerEvent::AnnonceAdded(annonce))
+        }
+        ServerEvent::AnnonceUpdated(annonce) if !annonce.targets.matches(device) => {
+            Some(ServerEvent::AnnonceDeleted { id: annonce.id })
+        }
+        ServerEvent::AnnonceToggled(annonce) if !annonce.targets.matches(device) => {
+            Some(ServerEvent::AnnonceDeleted { id: annonce.id })
+        }
+        ServerEvent::Annonces(annonces) => Some(ServerEvent::Annonces(
+            annonces.into_iter().filter(|a| a.targets.matches(device)).collect(),
+        )),
+        event => Some(event),
+    }
+}
@@ -0,0 +1,208 @@
+// Voice packs: calls assembled from recorded clips instead of a synthetic voice.
+//
+// A pack is a folder under `voice_packs/` holding WAV/MP3 clips and a `manifest.json`:
+//
+//     {
+//       "name": "Marie",
+//       "language": "fr-FR",
+//       "clips": {
+//         "client numéro": "client_numero.wav",
+//         "au guichet": "au_guichet.wav",
+//         "douze": "12.wav",
+//         "a": "letters/a.wav"
+//       }
+//     }
+//
+// Keys are words or whole phrases, matched against the rendered call after numbers
+// have been spelled out ("A012" is looked up as "a", "zéro", "douze"). The longest
+// phrase wins, and words without a clip are read by the TTS engine.
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use crate::error::{QmsError, QmsResult};
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Deserialize)]
+struct Manifest {
+    name: String,
+    language: String,
+    clips: HashMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct VoicePackInfo {
+    // Folder name, referenced by `LanguageAnnouncement::voice_pack`.
+    id: String,
+    name: String,
+    language: String,
+    phrases: usize,
+}
+
+// What to play for one part of a phrase.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    Clip(PathBuf),
+    // Words the pack has no clip for.
+    Speech(String),
+}
+
+pub struct VoicePack {
+    info: VoicePackInfo,
+    // Normalised phrase -> clip file
+    clips: HashMap<String, PathBuf>,
+    // Number of words in the longest phrase, bounds the lookahead.
+    longest: usize,
+}
+
+impl VoicePack {
+    fn load(id: String, dir: &Path) -> QmsResult<Self> {
+        let raw = fs::read_to_string(dir.join(MANIFEST))
+            .map_err(|e| QmsError::Validation(format!("Cannot read {} of '{}': {}", MANIFEST, id, e)))?;
+        let manifest: Manifest = serde_json::from_str(&raw)
+            .map_err(|e| QmsError::Validation(format!("Invalid {} in '{}': {}", MANIFEST, id, e)))?;
+
+        let mut clips = HashMap::new();
+        for (phrase, file) in manifest.clips {
+            let path = dir.join(&file);
+            // Clips must stay inside the pack folder.
+            let inside = Path::new(&file)
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)));
+            if !inside || !path.is_file() {
+                eprintln!("⚠️ Voice pack '{}': missing clip {} for \"{}\"", id, file, phrase);
+                continue;
+            }
+            let words = words(&phrase);
+            if !words.is_empty() {
+                clips.insert(words.join(" "), path);
+            }
+        }
+        let longest = clips.keys().map(|k| k.split(' ').count()).max().unwrap_or(0);
+
+        Ok(VoicePack {
+            info: VoicePackInfo {
+                id,
+                name: manifest.name,
+                language: manifest.language,
+                phrases: clips.len(),
+            },
+            clips,
+            longest,
+        })
+    }
+
+    // Splits the text into recorded clips and runs of words left to the TTS engine.
+    pub fn plan(&self, text: &str) -> Vec<Segment> {
+        let words = words(text);
+        let mut segments = Vec::new();
+        let mut missing: Vec<&str> = Vec::new();
+
+        let mut i = 0;
+        while i < words.len() {
+            let found = (1..=self.longest.min(words.len() - i))
+                .rev()
+                .find_map(|n| self.clips.get(&words[i..i + n].join(" ")).map(|clip| (n, clip)));
+            match found {
+                Some((n, clip)) => {
+                    if !missing.is_empty() {
+                        segments.push(Segment::Speech(missing.join(" ")));
+                        missing.clear();
+                    }
+                    segments.push(Segment::Clip(clip.clone()));
+                    i += n;
+                }
+                None => {
+                    missing.push(&words[i]);
+                    i += 1;
+                }
+            }
+        }
+        if !missing.is_empty() {
+            segments.push(Segment::Speech(missing.join(" ")));
+        }
+        segments
+    }
+}
+
+// Lowercase words, punctuation dropped: "Client numéro, dix-sept" ->
+// ["client", "numéro", "dix", "sept"]. Apostrophes stay ("l'accueil").
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '’'))
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase().replace('’', "'"))
+        .collect()
+}
+
+// Shared by the announcer thread and the clip renderer. Packs are read from disk at
+// startup and again on `reload`, never while announcing.
+#[derive(Clone)]
+pub struct VoicePacks {
+    dir: PathBuf,
+    packs: Arc<RwLock<HashMap<String, Arc<VoicePack>>>>,
+    // Bumped on every reload, so rendered clips can tell old recordings from new.
+    generation: Arc<AtomicU64>,
+}
+
+impl VoicePacks {
+    pub fn open(dir: PathBuf) -> Self {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("❌ Cannot create {}: {}", dir.display(), e);
+        }
+        let packs = VoicePacks {
+            dir,
+            packs: Arc::new(RwLock::new(HashMap::new())),
+            generation: Arc::new(AtomicU64::new(0)),
+        };
+        packs.reload();
+        packs
+    }
+
+    // A broken pack is reported and skipped; the others still load.
+    pub fn reload(&self) -> Vec<VoicePackInfo> {
+        let mut loaded = HashMap::new();
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.join(MANIFEST).is_file() {
+                    continue;
+                }
+                let id = entry.file_name().to_string_lossy().into_owned();
+                match VoicePack::load(id.clone(), &path) {
+                    Ok(pack) => {
+                        println!("🎙️ Voice pack '{}' loaded ({} phrases)", id, pack.info.phrases);
+                        loaded.insert(id, Arc::new(pack));
+                    }
+                    Err(e) => eprintln!("❌ Voice pack '{}' skipped: {}", id, e),
+                }
+            }
+        }
+
+        *self.packs.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.list()
+    }
+
+    pub fn list(&self) -> Vec<VoicePackInfo> {
+        let packs = self.packs.read().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<VoicePackInfo> = packs.values().map(|p| p.info.clone()).collect();
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        list
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<VoicePack>> {
+        self.packs.read().unwrap_or_else(|e| e.into_inner()).get(id).cloned()
+    }
+}
@@ -0,0 +1,233 @@
+// Customer-side metrics: how long people waited (issued -> called), how long they
+// were served (called -> done) and how many never showed up, per service, against
+// the SLA targets in settings.
+//
+// Waiting time needs the ticket to have been issued through `/issue` (or the
+// `issue_ticket` command); the desk that calls number N picks up the issue time of
+// ticket N of the same session. Service time needs the desk to press "done".
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::audit::{self, Audit};
+use crate::error::{QmsError, QmsResult};
+use crate::settings;
+use crate::stats::{self, StatsQuery};
+use crate::Database;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct SlaTarget {
+    // Share of tickets expected within these, reported as a compliance percentage.
+    pub wait_minutes: u32,
+    pub service_minutes: u32,
+}
+
+impl Default for SlaTarget {
+    fn default() -> Self {
+        SlaTarget {
+            wait_minutes: 15,
+            service_minutes: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct SlaSettings {
+    // Used by the site totals and by services without their own target.
+    pub default: SlaTarget,
+    pub services: BTreeMap<String, SlaTarget>,
+}
+
+impl SlaSettings {
+    pub fn validate(&self) -> QmsResult<()> {
+        let targets = std::iter::once(&self.default).chain(self.services.values());
+        for target in targets {
+            if !(1..=1440).contains(&target.wait_minutes) || !(1..=1440).contains(&target.service_minutes) {
+                return Err(QmsError::Validation("SLA targets must be between 1 and 1440 minutes".to_string()));
+            }
+        }
+        if self.services.keys().any(|s| s.trim().is_empty()) {
+            return Err(QmsError::Validation("SLA service name cannot be empty".to_string()));
+        }
+        Ok(())
+    }
+
+    fn target_for(&self, service: &str) -> SlaTarget {
+        self.services.get(service).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct IssuedTicket {
+    ticket_number: i32,
+    service: Option<String>,
+    // Local time, "YYYY-MM-DD HH:MM:SS"
+    issued_at: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DurationStats {
+    // Tickets the duration is known for.
+    count: u32,
+    pub avg_minutes: Option<f64>,
+    median_minutes: Option<f64>,
+    p90_minutes: Option<f64>,
+    target_minutes: u32,
+    // Share of `count` at or under the target.
+    within_target_percent: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WaitingStats {
+    // None for the site totals.
+    pub service: Option<String>,
+    pub called: u32,
+    pub no_shows: u32,
+    no_show_percent: Option<f64>,
+    pub waiting: DurationStats,
+    pub serving: DurationStats,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WaitingMetrics {
+    from: String,
+    to: String,
+    pub site: WaitingStats,
+    pub services: Vec<WaitingStats>,
+}
+
+#[derive(Default)]
+struct Samples {
+    called: u32,
+    no_shows: u32,
+    waiting: Vec<f64>,
+    serving: Vec<f64>,
+}
+
+impl Samples {
+    fn add(&mut self, no_show: bool, waiting: Option<f64>, serving: Option<f64>) {
+        self.called += 1;
+        if no_show {
+            self.no_shows += 1;
+        }
+        self.waiting.extend(waiting);
+        self.serving.extend(serving);
+    }
+
+    fn stats(self, service: Option<String>, target: SlaTarget) -> WaitingStats {
+        WaitingStats {
+            service,
+            called: self.called,
+            no_shows: self.no_shows,
+            no_show_percent: percent(self.no_shows as usize, self.called as usize),
+            waiting: durations(self.waiting, target.wait_minutes),
+            serving: durations(self.serving, target.service_minutes),
+        }
+    }
+}
+
+fn durations(mut minutes: Vec<f64>, target: u32) -> DurationStats {
+    minutes.sort_by(|a, b| a.total_cmp(b));
+    let within = minutes.iter().filter(|m| **m <= target as f64).count();
+    DurationStats {
+        count: minutes.len() as u32,
+        avg_minutes: (!minutes.is_empty()).then(|| round(minutes.iter().sum::<f64>() / minutes.len() as f64)),
+        median_minutes: stats::median(&minutes).map(round),
+        p90_minutes: stats::percentile(&minutes, 90.0).map(round),
+        target_minutes: target,
+        within_target_percent: percent(within, minutes.len()),
+    }
+}
+
+fn percent(part: usize, total: usize) -> Option<f64> {
+    (total > 0).then(|| round(part as f64 * 100.0 / total as f64))
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+impl Database {
+    // Numbers restart at 1 after every counter reset, like the calls do.
+    pub fn issue_ticket(&self, service: Option<String>, audit: &Audit) -> QmsResult<IssuedTicket> {
+        let service = service.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let ticket = tx.query_row(
+            "INSERT INTO issued_tickets (ticket_number, service, reset_id)
+             SELECT COALESCE(MAX(t.ticket_number), 0) + 1, ?1, r.reset_id
+             FROM (SELECT COALESCE(MAX(id), 0) AS reset_id FROM historique WHERE ticket_number = -2) r
+             LEFT JOIN issued_tickets t ON t.reset_id = r.reset_id
+             RETURNING ticket_number, service, datetime(issued_at, 'localtime')",
+            params![service],
+            |row| {
+                Ok(IssuedTicket {
+                    ticket_number: row.get(0)?,
+                    service: row.get(1)?,
+                    issued_at: row.get(2)?,
+                })
+            },
+        )?;
+        audit.record(&tx, None, audit::value(&ticket))?;
+        tx.commit()?;
+        Ok(ticket)
+    }
+
+    pub fn get_waiting_metrics(&self, query: &StatsQuery) -> QmsResult<WaitingMetrics> {
+        let (from, to) = query.range()?;
+        let sla: SlaSettings = self.get_setting(settings::SLA)?;
+        let conn = self.reader()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT service, status,
+                    (julianday(created_at) - julianday(issued_at)) * 1440,
+                    CASE WHEN status = 'served' THEN (julianday(ended_at) - julianday(created_at)) * 1440 END
+             FROM historique
+             WHERE ticket_number >= 0
+             AND date(created_at, 'localtime') BETWEEN ?1 AND ?2
+             AND (?3 IS NULL OR desk_name = ?3)
+             AND (?4 IS NULL OR service = ?4)
+             AND (?5 IS NULL OR operator_id = ?5)",
+        )?;
+        let desk = query.desk.as_deref().map(str::trim).filter(|d| !d.is_empty());
+        let service = query.service.as_deref().map(str::trim).filter(|s| !s.is_empty());
+        let iter = stmt.query_map(params![from.to_string(), to.to_string(), desk, service, query.operator_id], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<f64>>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+            ))
+        })?;
+
+        let mut site = Samples::default();
+        let mut services: BTreeMap<String, Samples> = BTreeMap::new();
+        for row in iter {
+            let (service, status, waiting, serving) = row?;
+            let no_show = status == "no_show";
+            // Clock changes can make a difference slightly negative.
+            let waiting = waiting.map(|m| m.max(0.0));
+            let serving = serving.map(|m| m.max(0.0));
+            site.add(no_show, waiting, serving);
+            if let Some(service) = service {
+                services.entry(service).or_default().add(no_show, waiting, serving);
+            }
+        }
+
+        Ok(WaitingMetrics {
+            from: from.to_string(),
+            to: to.to_string(),
+            site: site.stats(None, sla.default),
+            services: services
+                .into_iter()
+                .map(|(name, samples)| {
+                    let target = sla.target_for(&name);
+                    samples.stats(Some(name), target)
+                })
+                .collect(),
+        })
+    }
+}